@@ -2,6 +2,9 @@
 // A - Tokens for use by the lexer
 // B - Data structures for use by the parser
 // C - The data structure representation of diazo types. For use in the interpreter itself.
+// D - Errors reported by each stage of the language, with the location they were found at.
+// E - The graph of files brought in by *use, recorded by the lexer.
+// F - The concrete syntax tree, which keeps every character of the source for tools that write it back out.
 
 //A - Define tokens for the lexer to use
 pub mod tokens {
@@ -29,6 +32,7 @@ pub mod tokens {
         C(String),  //Content formatter for any type
         Any,    //Keyword to allow use of any type
         TypeAsDeclarationParameter(String), //Type used as a declaration parameter
+        Annotation(String), //A word starting with @ among the arguments, marking the type for tools such as exporters
         //Markup Content
         TypeInstance(String), //Types instantiated during markup
         Content(String), //Notes being marked up
@@ -37,19 +41,20 @@ pub mod tokens {
         CodeBlockClose,
         MathBlockOpen,
         MathBlockClose,
+        CodeFence(String),  //Opens a fenced code block, holding its language tag, which is empty if there isn't one. The lexer follows it with the block's CodeBlock.
         //Hidden tokens that are created and used by the parser rather than the lexer.
         Element, //An "element" that places the elements of collections as children under itself for later pattern matching.
         ContentWithFormatting(Vec<Tokens>), //Store a vector of content and formatting blocks if formatting blocks are found.
         MathBlock(String), //MathBlock. The lexer uses code block symbols to flank a Content token. These will be replaced with a single MathBlock token containing the appropriate text in the parser. 
-        CodeBlock(String), //Similar process to the above.
+        CodeBlock(String), //Similar process to the above. The lexer makes these itself for fenced code blocks, holding their lines exactly as written.
         Null    //Null token because Option<> syntax is annoying.
     }
 
     impl Tokens {
         pub fn print(&self) -> String {
             //String adder function to reduce boilerplate
-            fn string_adder(text: &str, s: &String) -> String {
-                    text.to_string() + s.as_str()
+            fn string_adder(text: &str, s: &str) -> String {
+                    text.to_string() + s
             }
             //Match each of the variants of a token with a printout
             match self {
@@ -68,12 +73,14 @@ pub mod tokens {
                 Tokens::C(s) => string_adder("Content Formatter Token for declarations containing: ", s),
                 Tokens::Any => "\"Any\" Keyword Token".to_string(),
                 Tokens::TypeAsDeclarationParameter(s) => string_adder("Type Used as Declaration Parameter, type: ", s),
+                Tokens::Annotation(s) => string_adder("Annotation Token containing: ", s),
                 Tokens::TypeInstance(s) => string_adder("Type Instance Token, type: ", s),
                 Tokens::Content(s) => string_adder("Content Token containing the text: ", s),
                 Tokens::CodeBlockOpen => "Open Code Block Symbol Token".to_string(),
                 Tokens::CodeBlockClose => "Close Code Block Symbol Token".to_string(),
                 Tokens::MathBlockOpen => "Open Math Block Symbol Token".to_string(),
                 Tokens::MathBlockClose => "Close Math Block Symbol Token".to_string(),
+                Tokens::CodeFence(s) => string_adder("Code Fence Token with the language: ", s),
                 Tokens::Element => "Element Node Token".to_string(),
                 Tokens::ContentWithFormatting(_v) => "Content and Formatting Container".to_string(),
                 Tokens::MathBlock(s) => string_adder("Math block containing the text: ", s),
@@ -81,6 +88,40 @@ pub mod tokens {
                 Tokens::Null => "Null Token".to_string(),
             }
         }
+
+        //The name of the variant, which stays the same between versions. Used when tokens are written out for other programs.
+        pub fn name(&self) -> &'static str {
+            match self {
+                Tokens::Separator(_) => "Separator",
+                Tokens::Tab => "Tab",
+                Tokens::Linebreak => "Linebreak",
+                Tokens::UseKeyword => "UseKeyword",
+                Tokens::Filename(_) => "Filename",
+                Tokens::CommentLine => "CommentLine",
+                Tokens::CommentOpen => "CommentOpen",
+                Tokens::CommentContents(_) => "CommentContents",
+                Tokens::TypeKeyword => "TypeKeyword",
+                Tokens::TypeName(_) => "TypeName",
+                Tokens::Assignment => "Assignment",
+                Tokens::E(_) => "E",
+                Tokens::C(_) => "C",
+                Tokens::Any => "Any",
+                Tokens::TypeAsDeclarationParameter(_) => "TypeAsDeclarationParameter",
+                Tokens::Annotation(_) => "Annotation",
+                Tokens::TypeInstance(_) => "TypeInstance",
+                Tokens::Content(_) => "Content",
+                Tokens::CodeBlockOpen => "CodeBlockOpen",
+                Tokens::CodeBlockClose => "CodeBlockClose",
+                Tokens::MathBlockOpen => "MathBlockOpen",
+                Tokens::MathBlockClose => "MathBlockClose",
+                Tokens::CodeFence(_) => "CodeFence",
+                Tokens::Element => "Element",
+                Tokens::ContentWithFormatting(_) => "ContentWithFormatting",
+                Tokens::MathBlock(_) => "MathBlock",
+                Tokens::CodeBlock(_) => "CodeBlock",
+                Tokens::Null => "Null",
+            }
+        }
     }
 
     impl Clone for Tokens {
@@ -101,12 +142,14 @@ pub mod tokens {
                 Tokens::C(s) => Tokens::C(s.clone()),
                 Tokens::Any => Tokens::Any,
                 Tokens::TypeAsDeclarationParameter(s) => Tokens::TypeAsDeclarationParameter(s.clone()),
+                Tokens::Annotation(s) => Tokens::Annotation(s.clone()),
                 Tokens::TypeInstance(s) => Tokens::TypeInstance(s.clone()),
                 Tokens::Content(s) => Tokens::Content(s.clone()),
                 Tokens::CodeBlockOpen => Tokens::CodeBlockOpen,
                 Tokens::CodeBlockClose => Tokens::CodeBlockClose,
                 Tokens::MathBlockOpen => Tokens::MathBlockOpen,
                 Tokens::MathBlockClose => Tokens::MathBlockClose,
+                Tokens::CodeFence(s) => Tokens::CodeFence(s.clone()),
                 Tokens::Element => Tokens::Element,
                 Tokens::ContentWithFormatting(v) => Tokens::ContentWithFormatting(v.clone()),
                 Tokens::MathBlock(s) => Tokens::MathBlock(s.clone()),
@@ -116,72 +159,149 @@ pub mod tokens {
         }
     }
     
+    //Where something was found in the source.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct Span {
+        pub file: Option<usize>,    //The file's index in the lexer's ImportGraph, or None for the input handed to the lexer.
+        pub offset: usize,          //Bytes from the start of the file.
+        pub line: usize,            //Line number, starting from 1. 0 for tokens that weren't read from any line, such as the line break after an import.
+        pub column: usize           //Characters from the start of the line, starting from 1.
+    }
+
+    //A token along with where it was found. Tokens holding text start where their first word does.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Token {
+        pub value: Tokens,
+        pub span: Span
+    }
+
     // Return a list of accepted separator variants
     pub fn separator_list() -> Vec<&'static str> {
         vec!["::", "->", ",,"]
     }
+
+    //Symbols that are written literally when a backslash is put in front of them, e.g. std\::vector. Two backslashes before one of them are a backslash of their own, e.g. C:\\:: path. Any other backslash is just a backslash.
+    pub fn escapable_list() -> Vec<&'static str> {
+        let mut output = separator_list();
+        output.extend(["[[", "]]", "{{", "}}", "//", "/*", "*/", "```"]);
+        output
+    }
+
+    //Find the first place a symbol appears in some text without a backslash in front of it, counting doubled backslashes as backslashes of their own.
+    pub fn find_unescaped(text: &str, symbol: &str) -> Option<usize> {
+        text.match_indices(symbol).map(|(at, _)| at).find(|&at| (text[..at].len() - text[..at].trim_end_matches('\\').len()).is_multiple_of(2))
+    }
 }
 
 //B - Define some data structures used for building the intermediate representation.
 pub mod parser_structs {
-    use std::{rc::Rc, cell::RefCell};
+    use std::{ops::Index, collections::VecDeque};
+
+    //The position of a node in its tree's arena.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub struct NodeId(pub usize);
 
 //Define the data structure of the nodes of the Abstract Syntax Tree.
-#[derive(Debug)]    //Try not to use this as much as possible.
+    #[derive(Debug, Clone, PartialEq)]
     pub struct TreeNode {
         pub value: super::tokens::Tokens,                  //The Token held in this node.
-        pub children: Vec<Rc<RefCell<TreeNode>>>,   //List of children.
-        pub parent: Option<Rc<RefCell<TreeNode>>>   //Parent node, of it exists.
+        pub span: super::tokens::Span,              //Where the token was found. Elements are placed at the tabs before them, and content with formatting at its first piece.
+        pub children: Vec<NodeId>,                  //List of children, in order.
+        pub parent: Option<NodeId>,                 //Parent node, if it exists.
+        pub index: usize                            //Position among the parent's children, so siblings are found without searching.
+    }
+
+    //An abstract syntax tree. Every node is kept in one vector and nodes refer to each other by their index, so there are no reference cycles to leak and the tree can be printed, cloned and sent to other threads.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Tree {
+        nodes: Vec<TreeNode>    //The arena. The root is always the first node.
     }
 
-    impl TreeNode {
+    impl Tree {
 
-        //Initialize A Tree Node
-        pub fn new(token: super::tokens::Tokens) -> TreeNode {
-            TreeNode { value: token, children: Vec::new(), parent: None }
+        //Start a tree with its root node.
+        pub fn new(token: super::tokens::Tokens, span: super::tokens::Span) -> Tree {
+            Tree { nodes: vec![TreeNode { value: token, span, children: Vec::new(), parent: None, index: 0 }] }
         }
 
-        //Add a new node to an existing node/subtree/tree
-        pub fn add(token: super::tokens::Tokens, existing_tree: &Rc<RefCell<TreeNode>>) {
-            let mut t = TreeNode::new(token);
-            t.parent = Some(Rc::clone(existing_tree));
-            existing_tree.borrow_mut().children.push(Rc::new(RefCell::new(t)));
+        pub fn root(&self) -> NodeId {
+            NodeId(0)
         }
 
-        //Add a new node to an existing node/subtree/tree and then set it as the current node
-        pub fn add_and_set(token: super::tokens::Tokens, existing_tree: &Rc<RefCell<TreeNode>>) -> Rc<RefCell<TreeNode>> {
-            TreeNode::add(token, existing_tree);
-            Rc::clone(&existing_tree.borrow().children[existing_tree.borrow().children.len() - 1])
+        //Add a new node under an existing one, returning the new node's id.
+        pub fn add(&mut self, token: super::tokens::Tokens, span: super::tokens::Span, parent: NodeId) -> NodeId {
+            let id = NodeId(self.nodes.len());
+            let index = self.nodes[parent.0].children.len();
+            self.nodes.push(TreeNode { value: token, span, children: Vec::new(), parent: Some(parent), index });
+            self.nodes[parent.0].children.push(id);
+            id
         }
 
-        //Move back a node and use a reference to the parent node.
-        pub fn back_to_parent(existing_tree: &Rc<RefCell<TreeNode>>) -> Rc<RefCell<TreeNode>> {
-            Rc::clone(existing_tree.borrow_mut().parent.as_ref().unwrap())
+        pub fn get(&self, id: NodeId) -> Option<&TreeNode> {
+            self.nodes.get(id.0)
         }
 
-        //Print out the node's contents to a string. Debug doesn't work on these since it tries to print a reference to the child, which references the parent, which references the child... and so on.
-        pub fn print(&self) -> String {
+        //A node along with this tree, for walking from it.
+        pub fn node(&self, id: NodeId) -> Node<'_> {
+            Node { tree: self, id }
+        }
+
+        pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+            self[id].parent
+        }
+
+        pub fn children(&self, id: NodeId) -> &[NodeId] {
+            &self[id].children
+        }
+
+        //The node after this one under the same parent, if there is one.
+        pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+            self.children(self.parent(id)?).get(self[id].index + 1).copied()
+        }
+
+        //The node before this one under the same parent, if there is one.
+        pub fn previous_sibling(&self, id: NodeId) -> Option<NodeId> {
+            self.children(self.parent(id)?).get(self[id].index.checked_sub(1)?).copied()
+        }
+
+        pub fn len(&self) -> usize {
+            self.nodes.len()
+        }
+
+        //Always false, since a tree has at least its root.
+        pub fn is_empty(&self) -> bool {
+            self.nodes.is_empty()
+        }
+
+        //Every node of the tree, in the order they were added.
+        pub fn iter(&self) -> impl Iterator<Item = Node<'_>> {
+            (0..self.nodes.len()).map(move |i| self.node(NodeId(i)))
+        }
+
+        //Print out a node's contents to a string, along with its children and parent.
+        pub fn print(&self, id: NodeId) -> String {
             //String adder function to reduce boilerplate
-            fn string_adder(text: &str, s: &String) -> String { text.to_string() + s.as_str() }
+            fn string_adder(text: &str, s: &str) -> String { text.to_string() + s }
 
+            let node = &self[id];
             let mut output: String = String::from("");  //String that will be assembled and eventually returned.
             let queue: Vec<String> = vec![              //Vector that will be iterated over and fed into String.
                 "AST Node Containing: ".to_string(),
-                string_adder("\n\tToken: ", &self.value.print()),
+                string_adder("\n\tToken: ", &node.value.print()),
                 "\n\tChildren: \n\t".to_string(),
-                match &self.children.len() {            //Check if there is anything in the children vector.
+                match &node.children.len() {            //Check if there is anything in the children vector.
                     0 => "\tNo children.".to_string(),  //If not, say so.
                     _ => {                              //Otherwise, assemble a String from the children of the node.
                         let mut t = String::new();
-                        for i in &self.children {
-                            t = t + i.borrow().value.print().as_str() + "\n\t\t";
+                        for i in &node.children {
+                            t = t + self[*i].value.print().as_str() + "\n\t\t";
                         }
                         t
                     }
                 },
                 "\n\tParent: ".to_string(),
-                match &self.parent {    //Check if the node has a parent, since that is an Option<T>.
-                    Some(x) => x.borrow().value.print(),
+                match node.parent {    //Check if the node has a parent, since that is an Option<T>.
+                    Some(x) => self[x].value.print(),
                     None => "No parent.".to_string()
                 }
             ];
@@ -194,72 +314,204 @@ pub mod parser_structs {
 
         //Read the tree using a preorder traversal method. Output a vector of the tokens contained inside the tree.
         pub fn preorder_read(&self) -> Vec<ReaderTuple> {
-            let mut output: Vec<ReaderTuple> = Vec::new();  //Initialize the output vector.
-            let mut children_found: bool = false;                   //Boolean to store wheether or not the node has children.
-            let mut id_counter: usize = 0;                              //Id counter that will be assigned to each node's token.
-            let mut child_counter: usize = 0;                        //Child counter that lets us loop until we have finished with all of the children.
-            let mut child_max: usize = 0;                               //Maximum number of children.
-            let mut parent_id: usize = 0;
-
-            let mut node:Rc<RefCell<TreeNode>> = Rc::new(RefCell::new(TreeNode::new(super::tokens::Tokens::Null)));
-
-            output.push(ReaderTuple(id_counter, None, self.value.clone()));
-
-            if self.children.len() > 0 { children_found = true; }   //If the first node has children, set the bool to true.
-            child_max = self.children.len() - 1;    //Set the max to the highest index in the vector, which is length - 1.
-            while children_found && child_counter <= child_max {    //As long as we have children, and we have not yet reached the last child...
-                id_counter += 1;    //Increment the id_counter.
-                node = Rc::clone(&self.children[child_counter]);    //Make this child the current node.
-                output.push(ReaderTuple(id_counter, Some(parent_id), node.borrow().value.clone())); //Push this node's information to the output stack.
-                if node.borrow().children.len() > 0 {
-                    children_found = true;  //Tell loop to keep looking at children.
-                    parent_id = id_counter; //Change the parent_id to the current id_counter since we are about to start looking at those children.
-                } else {
-                    children_found = false;
-                }   //Check if the node has children, and mutate the bool based on that.
+            self.preorder().collect()
+        }
+
+        //Walk the whole tree, visiting each node before its children.
+        pub fn preorder(&self) -> Preorder<'_> {
+            self.node(self.root()).preorder()
+        }
+
+        //Walk the whole tree, visiting each node after its children.
+        pub fn postorder(&self) -> Postorder<'_> {
+            self.node(self.root()).postorder()
+        }
+
+        //Walk the whole tree a level at a time.
+        pub fn breadth_first(&self) -> BreadthFirst<'_> {
+            self.node(self.root()).breadth_first()
+        }
+
+        //Hand every node of the tree to a visitor.
+        pub fn walk<V: Visitor>(&self, visitor: &mut V) {
+            self.node(self.root()).walk(visitor)
+        }
+
+    }
+
+    impl Index<NodeId> for Tree {
+        type Output = TreeNode;
+
+        fn index(&self, id: NodeId) -> &TreeNode {
+            &self.nodes[id.0]
+        }
+    }
+
+    //A node of a tree along with the tree itself, so that it can be walked from without passing both around.
+    #[derive(Debug, Clone, Copy)]
+    pub struct Node<'a> {
+        pub tree: &'a Tree,
+        pub id: NodeId
+    }
+
+    impl<'a> Node<'a> {
+        pub fn value(&self) -> &'a super::tokens::Tokens {
+            &self.tree[self.id].value
+        }
+
+        pub fn span(&self) -> super::tokens::Span {
+            self.tree[self.id].span
+        }
+
+        pub fn parent(&self) -> Option<Node<'a>> {
+            self.tree.parent(self.id).map(|p| self.tree.node(p))
+        }
+
+        pub fn children(&self) -> impl Iterator<Item = Node<'a>> + 'a {
+            let tree = self.tree;
+            tree.children(self.id).iter().map(move |&c| tree.node(c))
+        }
+
+        pub fn next_sibling(&self) -> Option<Node<'a>> {
+            self.tree.next_sibling(self.id).map(|s| self.tree.node(s))
+        }
+
+        pub fn previous_sibling(&self) -> Option<Node<'a>> {
+            self.tree.previous_sibling(self.id).map(|s| self.tree.node(s))
+        }
+
+        //The traversals below start from this node, so they cover only its subtree. Depths are counted from here.
+        pub fn preorder(&self) -> Preorder<'a> {
+            Preorder { tree: self.tree, stack: vec![(self.id, 0)] }
+        }
+
+        pub fn postorder(&self) -> Postorder<'a> {
+            Postorder { tree: self.tree, stack: vec![(self.id, 0, false)] }
+        }
+
+        pub fn breadth_first(&self) -> BreadthFirst<'a> {
+            BreadthFirst { tree: self.tree, queue: VecDeque::from([(self.id, 0)]) }
+        }
+
+        pub fn walk<V: Visitor>(&self, visitor: &mut V) {
+            //Local function so that the depth can be carried down.
+            fn visit<V: Visitor>(node: Node, depth: usize, visitor: &mut V) {
+                if visitor.enter(node, depth) {
+                    for i in node.children() {
+                        visit(i, depth + 1, visitor);
+                    }
+                }
+                visitor.leave(node, depth);
             }
-            output
+            visit(*self, 0, visitor)
+        }
+    }
+
+    //What the traversals give for each node: its id, its parent's id, its depth, and its token.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ReaderTuple(pub NodeId, pub Option<NodeId>, pub usize, pub super::tokens::Tokens);
+
+    impl ReaderTuple {
+        fn new(tree: &Tree, id: NodeId, depth: usize) -> ReaderTuple {
+            ReaderTuple(id, tree.parent(id), depth, tree[id].value.clone())
+        }
+    }
+
+    pub struct Preorder<'a> {
+        tree: &'a Tree,
+        stack: Vec<(NodeId, usize)>     //Nodes still to visit, with the next one on top.
+    }
+
+    impl Iterator for Preorder<'_> {
+        type Item = ReaderTuple;
+
+        fn next(&mut self) -> Option<ReaderTuple> {
+            let (id, depth) = self.stack.pop()?;
+            self.stack.extend(self.tree.children(id).iter().rev().map(|&c| (c, depth + 1)));    //Reversed, so that the first child comes off the stack first.
+            Some(ReaderTuple::new(self.tree, id, depth))
         }
+    }
 
+    pub struct Postorder<'a> {
+        tree: &'a Tree,
+        stack: Vec<(NodeId, usize, bool)>   //Nodes still to visit, and whether their children have been put on the stack yet.
     }
 
-    //A tuple struct for use in the impl block of TreeNodes.
-    pub struct ReaderTuple(usize, Option<usize>, super::tokens::Tokens);
+    impl Iterator for Postorder<'_> {
+        type Item = ReaderTuple;
+
+        fn next(&mut self) -> Option<ReaderTuple> {
+            loop {
+                let (id, depth, expanded) = self.stack.pop()?;
+                if expanded || self.tree.children(id).is_empty() {
+                    return Some(ReaderTuple::new(self.tree, id, depth))
+                }
+                self.stack.push((id, depth, true));     //Come back to the node once its children are done.
+                self.stack.extend(self.tree.children(id).iter().rev().map(|&c| (c, depth + 1, false)));
+            }
+        }
+    }
+
+    pub struct BreadthFirst<'a> {
+        tree: &'a Tree,
+        queue: VecDeque<(NodeId, usize)>
+    }
+
+    impl Iterator for BreadthFirst<'_> {
+        type Item = ReaderTuple;
+
+        fn next(&mut self) -> Option<ReaderTuple> {
+            let (id, depth) = self.queue.pop_front()?;
+            self.queue.extend(self.tree.children(id).iter().map(|&c| (c, depth + 1)));
+            Some(ReaderTuple::new(self.tree, id, depth))
+        }
+    }
+
+    //Something that walks a tree, such as an exporter or a linter. Each node is entered before its children and left after them.
+    pub trait Visitor {
+        //Return false to skip the node's children. The node itself is still left afterwards.
+        fn enter(&mut self, _node: Node, _depth: usize) -> bool {
+            true
+        }
+
+        fn leave(&mut self, _node: Node, _depth: usize) {}
+    }
 
     //As the parser builds abstract syntax trees, it will need to sort through different tokens, some of which might not be necessary.
     //This enum's variants represent what is actually used later on in the interpreter, with other tokens being left behind or used to help build the tree.
     
-    #[derive(Debug)]
+    #[derive(Debug, Clone, PartialEq)]
     pub enum IrElements{
-        TypeDeclaration(Rc<RefCell<TreeNode>>),
-        TypeExpression(Rc<RefCell<TreeNode>>),
-        RawText(Rc<RefCell<TreeNode>>)
+        TypeDeclaration(Tree),
+        TypeExpression(Tree),
+        RawText(Tree)
     }
 
     impl IrElements{
         pub fn print(&self) -> String {
             match self {
-                IrElements::RawText(n) | IrElements::TypeDeclaration(n) | IrElements::TypeExpression(n) => {
-                    if n.borrow().children.len() > 0 {
-                        for i in &n.borrow().children {
-                            println!("{}", TreeNode::print(&i.borrow()));
-                            if i.borrow().children.len() > 0 {
-                                for j in &i.borrow().children {
-                                    println!("{}", TreeNode::print(&j.borrow()));
-                                }
-                            }
+                IrElements::RawText(t) | IrElements::TypeDeclaration(t) | IrElements::TypeExpression(t) => {
+                    for i in t.children(t.root()) {
+                        println!("{}", t.print(*i));
+                        for j in t.children(*i) {
+                            println!("{}", t.print(*j));
                         }
                     }
-                    TreeNode::print(&n.borrow())
+                    t.print(t.root())
                 }
             }
         }
     
-        pub fn unwrap(self) -> Option<Rc<RefCell<TreeNode>>> {
-            if let IrElements::RawText(n) | IrElements::TypeDeclaration(n) | IrElements::TypeExpression(n) = self {
-                Some(n)
-            } else {
-                None
+        pub fn unwrap(self) -> Option<Tree> {
+            match self {
+                IrElements::RawText(t) | IrElements::TypeDeclaration(t) | IrElements::TypeExpression(t) => Some(t)
+            }
+        }
+
+        //The statement's tree.
+        pub fn tree(&self) -> &Tree {
+            match self {
+                IrElements::RawText(t) | IrElements::TypeDeclaration(t) | IrElements::TypeExpression(t) => t
             }
         }
     }
@@ -267,12 +519,610 @@ pub mod parser_structs {
 
 //C - Define the data structure of diazo types.
 pub mod interpreter_structs {
-    
+    use super::{tokens::Tokens, parser_structs::Node};
+
+    //How many times something may be repeated. Written as nothing, "..2" or "..n" after a c or e.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Count {
+        Fixed(usize),   //An exact count, such as the 2 in c..2.
+        Variable        //The n in c..n, which is decided by the expression but has to agree everywhere in it.
+    }
+
+    impl Count {
+        //Read the count following a c or e argument, e.g. "c..2" gives Fixed(2). A bare letter is treated as the default count given.
+        fn read(argument: &str, default: Count) -> Result<Count, &'static str> {
+            if argument.len() == 1 {
+                return Ok(default)
+            }
+            match &argument[1..] {
+                "..n" => Ok(Count::Variable),
+                other => match other.strip_prefix("..").and_then(|c| c.parse::<usize>().ok()) {
+                    Some(0) => Err("Invalid declaration (Interpreter): An argument can't be repeated 0 times."),
+                    Some(c) => Ok(Count::Fixed(c)),
+                    None => Err("Invalid declaration (Interpreter): Only a number or n can follow the \"..\" of a c or e argument.")
+                }
+            }
+        }
+
+        pub fn print(&self) -> String {
+            match self {
+                Count::Fixed(c) => c.to_string(),
+                Count::Variable => "n".to_string()
+            }
+        }
+    }
+
+    //A single argument in a type declaration, describing one part of an expression of that type.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum DeclarationParameter {
+        Content(Count),     //c, c..2 or c..n. Content fields separated by separators.
+        Type(String),       //A type that has already been declared, written inline (e.g. the l in "def => l c").
+        Any                 //The any keyword, allowing any type or plain content.
+    }
+
+    impl DeclarationParameter {
+        //Convert one of the lexer's declaration argument tokens.
+        pub fn new(token: &Tokens) -> Result<DeclarationParameter, &'static str> {
+            match token {
+                Tokens::C(s) => Ok(DeclarationParameter::Content(Count::read(s, Count::Fixed(1))?)),
+                Tokens::TypeAsDeclarationParameter(s) => Ok(DeclarationParameter::Type(s.clone())),
+                Tokens::Any => Ok(DeclarationParameter::Any),
+                _ => Err("Invalid declaration (Interpreter): Found something other than c, any, or a type name in a declaration's arguments.")
+            }
+        }
+
+        //Write the parameter out the same way it appears in a declaration.
+        pub fn print(&self) -> String {
+            match self {
+                DeclarationParameter::Content(Count::Fixed(1)) => "c".to_string(),
+                DeclarationParameter::Content(c) => "c..".to_string() + c.print().as_str(),
+                DeclarationParameter::Type(s) => s.clone(),
+                DeclarationParameter::Any => "any".to_string()
+            }
+        }
+    }
+
+    //What each element of a collection type holds, from the arguments after the "e".
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ElementSpec {
+        pub count: Count,                           //How many elements the collection holds. A bare e allows any number.
+        pub parameters: Vec<DeclarationParameter>   //What a single element is made of.
+    }
+
+    //The abstract form of a type as written in its declaration statement. Type expressions are pattern matched against these.
+    #[derive(Debug, Clone, PartialEq)]
     pub struct AbstractDType {
+        pub name: String,                           //The name of the declared type.
+        pub header: Vec<DeclarationParameter>,      //The arguments before the element argument, in order.
+        pub elements: Option<ElementSpec>,          //What the elements look like, if the type is a collection.
+        pub annotations: Vec<String>                //The annotations written in the declaration, without the @.
+    }
+
+    impl AbstractDType {
+        //Build an abstract type from the tree of a type declaration statement. (TypeKeyword -> TypeName, Assignment -> arguments, E -> arguments)
+        pub fn new(declaration: Node) -> Result<AbstractDType, &'static str> {
+            if *declaration.value() != Tokens::TypeKeyword {
+                return Err("Invalid declaration (Interpreter): The root of a type declaration statement should be the type keyword.")
+            }
+            let mut name: Option<String> = None;
+            let mut header: Vec<DeclarationParameter> = Vec::new();
+            let mut elements: Option<ElementSpec> = None;
+            let mut annotations: Vec<String> = Vec::new();
+            for i in declaration.children() {
+                match i.value() {
+                    Tokens::TypeName(s) => name = Some(s.clone()),
+                    Tokens::Annotation(s) => annotations.push(s.clone()),
+                    Tokens::Assignment => {
+                        for j in i.children() {
+                            match j.value() {
+                                Tokens::E(e) => {   //Everything nested under the element argument describes a single element of the collection.
+                                    let mut parameters = Vec::new();
+                                    for k in j.children() {
+                                        parameters.push(DeclarationParameter::new(k.value())?);
+                                    }
+                                    if parameters.is_empty() {
+                                        return Err("Invalid declaration (Interpreter): The element argument needs to be followed by what the elements are made of.")
+                                    }
+                                    elements = Some(ElementSpec { count: Count::read(e, Count::Variable)?, parameters });
+                                },
+                                other => header.push(DeclarationParameter::new(other)?)
+                            }
+                        }
+                    },
+                    _ => return Err("Invalid declaration (Interpreter): Found something other than a type name, assignment symbol or annotation under the type keyword.")
+                }
+            }
+            match name {
+                Some(name) => Ok(AbstractDType { name, header, elements, annotations }),
+                None => Err("Invalid declaration (Interpreter): Type declaration statement is missing the name of the type.")
+            }
+        }
+
+        //Write out the arguments of the declaration, e.g. "l c..n e c c..n" for tabl.
+        pub fn signature(&self) -> String {
+            let mut words: Vec<String> = self.header.iter().map(|p| p.print()).collect();
+            if let Some(e) = &self.elements {
+                words.push(match e.count {
+                    Count::Variable => "e".to_string(),
+                    c => "e..".to_string() + c.print().as_str()
+                });
+                words.extend(e.parameters.iter().map(|p| p.print()));
+            }
+            words.join(" ")
+        }
+
+        //Whether the declaration carries an annotation, given without the @.
+        pub fn annotated(&self, annotation: &str) -> bool {
+            self.annotations.iter().any(|a| a == annotation)
+        }
+    }
 
+    //Every type that has been declared, in the order the declarations were found.
+    #[derive(Debug, Clone, Default)]
+    pub struct TypeRegistry {
+        types: Vec<AbstractDType>
     }
 
+    impl TypeRegistry {
+        pub fn new() -> TypeRegistry {
+            TypeRegistry { types: Vec::new() }
+        }
+
+        //Add a type to the registry, as long as its name isn't taken and the types it uses inline are already known.
+        pub fn declare(&mut self, dtype: AbstractDType) -> Result<(), &'static str> {
+            if self.get(&dtype.name).is_some() {
+                return Err("Invalid declaration (Interpreter): It appears this type has been declared before, the namespace is already occupied!")
+            }
+            let mut parameters: Vec<&DeclarationParameter> = dtype.header.iter().collect();
+            if let Some(e) = &dtype.elements {
+                parameters.extend(e.parameters.iter());
+            }
+            for i in parameters {
+                if let DeclarationParameter::Type(s) = i {
+                    if self.get(s).is_none() {
+                        return Err("Invalid declaration (Interpreter): A type used as an argument has not been declared yet.")
+                    }
+                }
+            }
+            self.types.push(dtype);
+            Ok(())
+        }
+
+        //Look up a type by its name.
+        pub fn get(&self, name: &str) -> Option<&AbstractDType> {
+            self.types.iter().find(|t| t.name == name)
+        }
+
+        //Iterate over the declared types in the order they were declared.
+        pub fn iter(&self) -> std::slice::Iter<'_, AbstractDType> {
+            self.types.iter()
+        }
+
+        //The names of every declared type.
+        pub fn names(&self) -> Vec<&str> {
+            self.types.iter().map(|t| t.name.as_str()).collect()
+        }
+
+        pub fn len(&self) -> usize {
+            self.types.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.types.is_empty()
+        }
+    }
+
+    //The name of the prelude's label type. Fields filled by an l argument become the label of an object rather than part of its contents.
+    pub const LABEL_TYPE: &str = "l";
+
+    //A piece of formatted content, taken from a ContentWithFormatting token.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Fragment {
+        Text(String),   //Plain content.
+        Code(String),   //The contents of a [[ ]] code block.
+        Math(String),   //The contents of a {{ }} math block.
+        Fenced { language: Option<String>, code: String }   //A fenced code block, with its lines exactly as written.
+    }
+
+    impl Fragment {
+        pub fn text(&self) -> &str {
+            match self {
+                Fragment::Text(s) | Fragment::Code(s) | Fragment::Math(s) | Fragment::Fenced { code: s, .. } => s
+            }
+        }
+    }
+
+    //A single content field of an expression, i.e. whatever sits between two separators.
+    #[derive(Debug, Clone, PartialEq, Default)]
+    pub struct Field {
+        pub fragments: Vec<Fragment>
+    }
+
+    impl Field {
+        //Convert the contents of a ContentWithFormatting token, trimming the whitespace the lexer leaves around the edges of the field.
+        pub fn new(tokens: &[Tokens]) -> Field {
+            let mut fragments: Vec<Fragment> = Vec::new();
+            let mut fence: Option<&String> = None;  //The language of the fenced code block coming up next.
+            for i in tokens {
+                match i {
+                    Tokens::CodeFence(s) => fence = Some(s),
+                    Tokens::CodeBlock(s) if fence.is_some() => {
+                        let language = fence.take().filter(|l| !l.is_empty()).cloned();
+                        fragments.push(Fragment::Fenced { language, code: s.clone() });
+                    },
+                    Tokens::Content(s) if !fragments.is_empty() => fragments.push(Fragment::Text(" ".to_string() + s)),  //The lexer drops the whitespace between a block and the words after it.
+                    Tokens::Content(s) => fragments.push(Fragment::Text(s.clone())),
+                    Tokens::CodeBlock(s) => fragments.push(Fragment::Code(s.trim().to_string())),
+                    Tokens::MathBlock(s) => fragments.push(Fragment::Math(s.trim().to_string())),
+                    _ => ()
+                }
+            }
+            if let Some(Fragment::Text(s)) = fragments.first_mut() {
+                *s = s.trim_start().to_string();
+            }
+            if let Some(Fragment::Text(s)) = fragments.last_mut() {
+                *s = s.trim_end().to_string();
+            }
+            fragments.retain(|f| !matches!(f, Fragment::Text(s) if s.is_empty()));
+            Field { fragments }
+        }
+
+        //The text of the field with formatting dropped.
+        pub fn text(&self) -> String {
+            self.fragments.iter().map(|f| f.text()).collect()
+        }
+    }
+
+    //An interpreted expression: the document model that exporters and other tools work with.
+    #[derive(Debug, Clone, PartialEq, Default)]
     pub struct DiazoObject {
+        pub type_name: Option<String>,      //The type of the expression. None for raw text and for elements that aren't a declared type (such as the rows of a tabl).
+        pub label: Option<Field>,           //The field filled by the l argument, if the type has one.
+        pub contents: Vec<Field>,           //Every other field, in the order they were written.
+        pub elements: Vec<DiazoObject>      //The elements of a collection, in order.
+    }
+
+    impl DiazoObject {
+        //Iterate over this object and everything nested in it, in preorder.
+        pub fn iter(&self) -> Objects<'_> {
+            Objects { stack: vec![self] }
+        }
+    }
+
+    //Preorder iterator over an object and its elements.
+    pub struct Objects<'a> {
+        stack: Vec<&'a DiazoObject>
+    }
+
+    impl<'a> Iterator for Objects<'a> {
+        type Item = &'a DiazoObject;
+
+        fn next(&mut self) -> Option<&'a DiazoObject> {
+            let next = self.stack.pop()?;
+            self.stack.extend(next.elements.iter().rev());  //Push the elements backwards so the first one is read next.
+            Some(next)
+        }
+    }
+}
+//D - Define the errors that the lexer, parser and interpreter report.
+pub mod errors {
+    use std::fmt;
+    use super::{tokens::Span, imports::ImportGraph};
+
+    //Machine-readable kinds of errors, so that tools don't need to read the messages.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ErrorKind {
+        //Lexer
+        TypeInScope,                    //*type placed after tabs.
+        RepeatedUse,                    //More than one *use on a line.
+        UseInScope,                     //*use placed after tabs.
+        UseInDeclaration,               //*use placed in a type declaration.
+        MisplacedAssignment,            //=> used outside of a type declaration.
+        MultiWordTypeName,              //More than one word between *type and =>.
+        DuplicateType,                  //A type name that has already been declared.
+        InvalidElementArgument,         //Something other than ".." following an e.
+        InvalidContentArgument,         //Something other than ".." following a c.
+        UnknownDeclarationArgument,     //Something other than c, e, any or a declared type in a declaration.
+        UnmatchedCommentClose,          //*/ without an open block comment.
+        Import,                         //A file given to *use could not be read.
+        ImportCycle,                    //A file that ends up importing itself through *use.
+        ImportNotAllowed,               //*use where the lexer has been told not to import anything.
+        MixedIndentation,               //A line indented with both tabs and spaces.
+        UnevenIndentation,              //A line indented by spaces that don't make up a whole number of levels.
+        IndentPragma,                   //An *indent pragma that isn't at the top of the file, or doesn't say tabs or a number of spaces.
+        UnclosedCodeFence,              //A fenced code block still open at the end of the input.
+        //Parser
+        NestedUnderDeclaration,         //An indented line under a type declaration.
+        InvalidTreeStructure,           //A statement or expression whose tree doesn't have a valid root.
+        MisplacedToken,                 //A token that can't start an expression.
+        IndentedOutsideExpression,      //An indented line with nothing to be nested under.
+        OverIndented,                   //A line indented further than the expression above allows.
+        MisplacedTypeKeyword,           //*type inside another statement or expression.
+        MisplacedDeclarationArgument,   //Type names, =>, c, e, any or type arguments in the wrong place.
+        MisplacedTypeExpression,        //A type instance somewhere that one can't be placed.
+        IllegalToken,                   //Tokens that only the parser can create found in its input.
+        //Interpreter
+        InvalidDeclaration,             //A type declaration that can't be turned into an abstract type.
+        TypeMismatch,                   //An expression that doesn't match the declaration of its type.
+    }
+
+    impl ErrorKind {
+        //A stable identifier for the kind of error, e.g. for editors and CI output.
+        pub fn code(&self) -> &'static str {
+            match self {
+                ErrorKind::TypeInScope => "type-in-scope",
+                ErrorKind::RepeatedUse => "repeated-use",
+                ErrorKind::UseInScope => "use-in-scope",
+                ErrorKind::UseInDeclaration => "use-in-declaration",
+                ErrorKind::MisplacedAssignment => "misplaced-assignment",
+                ErrorKind::MultiWordTypeName => "multi-word-type-name",
+                ErrorKind::DuplicateType => "duplicate-type",
+                ErrorKind::InvalidElementArgument => "invalid-element-argument",
+                ErrorKind::InvalidContentArgument => "invalid-content-argument",
+                ErrorKind::UnknownDeclarationArgument => "unknown-declaration-argument",
+                ErrorKind::UnmatchedCommentClose => "unmatched-comment-close",
+                ErrorKind::Import => "import",
+                ErrorKind::ImportCycle => "import-cycle",
+                ErrorKind::ImportNotAllowed => "import-not-allowed",
+                ErrorKind::MixedIndentation => "mixed-indentation",
+                ErrorKind::UnevenIndentation => "uneven-indentation",
+                ErrorKind::IndentPragma => "indent-pragma",
+                ErrorKind::UnclosedCodeFence => "unclosed-code-fence",
+                ErrorKind::NestedUnderDeclaration => "nested-under-declaration",
+                ErrorKind::InvalidTreeStructure => "invalid-tree-structure",
+                ErrorKind::MisplacedToken => "misplaced-token",
+                ErrorKind::IndentedOutsideExpression => "indented-outside-expression",
+                ErrorKind::OverIndented => "over-indented",
+                ErrorKind::MisplacedTypeKeyword => "misplaced-type-keyword",
+                ErrorKind::MisplacedDeclarationArgument => "misplaced-declaration-argument",
+                ErrorKind::MisplacedTypeExpression => "misplaced-type-expression",
+                ErrorKind::IllegalToken => "illegal-token",
+                ErrorKind::InvalidDeclaration => "invalid-declaration",
+                ErrorKind::TypeMismatch => "type-mismatch",
+            }
+        }
+    }
+
+    //Where in the source an error was found.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct Location {
+        pub file: Option<String>,   //The file the error is in. None for the input handed to the lexer directly.
+        pub line: usize,            //Line number, starting from 1.
+        pub column: Option<usize>,  //Column in the line, starting from 1. Known to the stages that read tokens, which carry spans.
+        pub word: Option<usize>     //Word number in the line, starting from 1. Only the lexer counts words.
+    }
+
+    impl fmt::Display for Location {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            if let Some(file) = &self.file {
+                write!(f, "{}, ", file)?;
+            }
+            write!(f, "line {}", self.line)?;
+            if let Some(column) = self.column {
+                write!(f, ", column {}", column)?;
+            }
+            if let Some(word) = self.word {
+                write!(f, ", word {}", word)?;
+            }
+            Ok(())
+        }
+    }
+
+    //An error from one of the stages of the language.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum DiazoError {
+        Lexer { kind: ErrorKind, location: Location, token: String, message: String },
+        //The parser and interpreter know where an error is from the span of a token, which only has the file's index in the import graph. The file is None until name_file is given the graph.
+        Parser { kind: ErrorKind, file: Option<String>, span: Span, token: String, message: String },
+        Interpreter { kind: ErrorKind, file: Option<String>, span: Span, expression: String, message: String },  //Located at the part of the expression or declaration that failed. The expression is written out too.
+        Io { kind: ErrorKind, file: String, message: String }
+    }
+
+    impl DiazoError {
+        pub fn kind(&self) -> ErrorKind {
+            match self {
+                DiazoError::Lexer { kind, .. } | DiazoError::Parser { kind, .. }
+                | DiazoError::Interpreter { kind, .. } | DiazoError::Io { kind, .. } => *kind
+            }
+        }
 
+        pub fn message(&self) -> &str {
+            match self {
+                DiazoError::Lexer { message, .. } | DiazoError::Parser { message, .. }
+                | DiazoError::Interpreter { message, .. } | DiazoError::Io { message, .. } => message
+            }
+        }
+
+        //The location of the error, if the stage that found it knows one.
+        pub fn location(&self) -> Option<Location> {
+            match self {
+                DiazoError::Lexer { location, .. } => Some(location.clone()),
+                DiazoError::Parser { file, span, .. } | DiazoError::Interpreter { file, span, .. } => Some(Location { file: file.clone(), line: span.line, column: Some(span.column), word: None }),
+                DiazoError::Io { .. } => None
+            }
+        }
+
+        //Where the token the error was found at is, for errors from the stages after the lexer.
+        pub fn span(&self) -> Option<Span> {
+            match self {
+                DiazoError::Parser { span, .. } | DiazoError::Interpreter { span, .. } => Some(*span),
+                _ => None
+            }
+        }
+
+        //Name the file an error from the parser or interpreter is in, going by the graph of the lexer that read it. Spans outside any import are in source.
+        pub fn name_file(&mut self, graph: &ImportGraph, source: Option<&str>) {
+            if let DiazoError::Parser { file, span, .. } | DiazoError::Interpreter { file, span, .. } = self {
+                *file = match span.file {
+                    Some(i) => graph.files.get(i).cloned(),
+                    None => source.map(str::to_string)
+                };
+            }
+        }
     }
-}
\ No newline at end of file
+
+    impl fmt::Display for DiazoError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                DiazoError::Lexer { token, message, .. } | DiazoError::Parser { token, message, .. } => {
+                    write!(f, "Issue found at {}, token: {}\n{}", self.location().unwrap_or_default(), token, message)
+                },
+                DiazoError::Interpreter { expression, message, .. } => write!(f, "Issue found at {}, in expression: {}\n{}", self.location().unwrap_or_default(), expression, message),
+                DiazoError::Io { file, message, .. } => write!(f, "Issue found while reading {}\n{}", file, message)
+            }
+        }
+    }
+
+    impl std::error::Error for DiazoError {}
+}
+
+//E - Define the record of which files import which.
+pub mod imports {
+    //Every file brought in by *use, directly or through another import. Each file is only lexed once, however many files import it.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct ImportGraph {
+        pub files: Vec<String>,                     //Imported files as they were found on disk, in the order they were first imported.
+        pub edges: Vec<(Option<usize>, usize)>,     //(importer, imported) pairs of indices into files. None is the input handed to the lexer.
+        pub(crate) types: Vec<Vec<String>>,         //The types each file makes available, so a repeated import can still hand them out.
+        pub(crate) chain: Vec<usize>                //The files currently being lexed, outermost first. Used to catch cycles.
+    }
+
+    impl ImportGraph {
+        pub fn new() -> ImportGraph {
+            ImportGraph::default()
+        }
+
+        //Index of a file in the graph, if it has been imported.
+        pub fn index(&self, file: &str) -> Option<usize> {
+            self.files.iter().position(|f| f == file)
+        }
+
+        //The files imported directly by a file, or by the input handed to the lexer when given None.
+        pub fn imports_of(&self, importer: Option<&str>) -> Vec<&str> {
+            let importer = match importer {
+                Some(file) => match self.index(file) {
+                    Some(i) => Some(i),
+                    None => return Vec::new()
+                },
+                None => None
+            };
+            self.edges.iter().filter(|(from, _)| *from == importer).map(|(_, to)| self.files[*to].as_str()).collect()
+        }
+
+        //The imported file that declares a type itself, rather than passing it on from one of its own imports.
+        pub fn declared_in(&self, name: &str) -> Option<&str> {
+            let passed_on = |i: usize| self.edges.iter().any(|(from, to)| *from == Some(i) && self.types[*to].iter().any(|t| t == name));
+            (0..self.files.len()).find(|&i| self.types[i].iter().any(|t| t == name) && !passed_on(i)).map(|i| self.files[i].as_str())
+        }
+
+        //Add a file that is about to be lexed, returning its index.
+        pub(crate) fn add(&mut self, file: &str) -> usize {
+            self.files.push(file.to_string());
+            self.types.push(Vec::new());
+            self.files.len() - 1
+        }
+    }
+}
+
+//F - Define the concrete syntax tree. Unlike the tokens, nothing is thrown away: joining the text of every leaf gives back the source.
+pub mod syntax {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SyntaxKind {
+        //Nodes, whose text is that of their children.
+        Document,
+        DeclarationLine,    //A line starting with *type.
+        UseLine,            //A line starting with *use.
+        ExpressionLine,     //A line of markup: an expression, one of its elements, or raw text.
+        CommentLine,        //A line holding nothing but comments.
+        BlankLine,          //A line holding nothing but whitespace.
+        CodeLine,           //A line of a fenced code block, or the fence closing it. These are left exactly as they are.
+        //Leaves, which hold the source text.
+        Indent,             //The whitespace a line starts with. Each tab is a level of scope.
+        Whitespace,         //Whitespace anywhere else in a line.
+        Newline,            //The line ending, "\n" or "\r\n". The last line might not have one.
+        Comment,            //A line comment to the end of the line, or as much of a block comment as is on this line.
+        TypeKeyword,
+        TypeName,
+        Assignment,
+        Parameter,          //c, e, any or a type name among the arguments of a declaration.
+        Annotation,         //A word starting with @ among the arguments of a declaration, @ included.
+        UseKeyword,
+        Filename,
+        Word,               //Anything else. Which words are types is only known once declarations and imports are read.
+        Separator,
+        CodeOpen,
+        CodeClose,
+        MathOpen,
+        MathClose,
+        Fence,              //```, followed by the language on the line opening a fenced code block.
+        Code                //A line inside a fenced code block, without its line ending.
+    }
+
+    impl SyntaxKind {
+        pub fn is_line(&self) -> bool {
+            matches!(self, SyntaxKind::DeclarationLine | SyntaxKind::UseLine | SyntaxKind::ExpressionLine | SyntaxKind::CommentLine | SyntaxKind::BlankLine | SyntaxKind::CodeLine)
+        }
+
+        pub fn is_leaf(&self) -> bool {
+            *self != SyntaxKind::Document && !self.is_line()
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct SyntaxNode {
+        pub kind: SyntaxKind,
+        pub text: String,               //The source text of a leaf. Empty for nodes.
+        pub children: Vec<SyntaxNode>   //Empty for leaves.
+    }
+
+    impl SyntaxNode {
+        pub fn leaf(kind: SyntaxKind, text: &str) -> SyntaxNode {
+            SyntaxNode { kind, text: text.to_string(), children: Vec::new() }
+        }
+
+        pub fn node(kind: SyntaxKind, children: Vec<SyntaxNode>) -> SyntaxNode {
+            SyntaxNode { kind, text: String::new(), children }
+        }
+
+        //Write the source back out, exactly as it was read.
+        pub fn source(&self) -> String {
+            let mut output = String::new();
+            self.write_source(&mut output);
+            output
+        }
+
+        fn write_source(&self, output: &mut String) {
+            output.push_str(&self.text);
+            for i in &self.children {
+                i.write_source(output);
+            }
+        }
+
+        //The leaves under this node in source order, or the node itself if it is a leaf.
+        pub fn leaves(&self) -> Vec<&SyntaxNode> {
+            if self.kind.is_leaf() {
+                return vec![self]
+            }
+            self.children.iter().flat_map(|i| i.leaves()).collect()
+        }
+
+        //Print out the tree, one node per line and indented by depth in the tree.
+        pub fn print(&self) -> String {
+            let mut output = String::new();
+            self.write_print(0, &mut output);
+            output
+        }
+
+        fn write_print(&self, depth: usize, output: &mut String) {
+            output.push_str(&"  ".repeat(depth));
+            if self.kind.is_leaf() {
+                output.push_str(&format!("{:?} {:?}\n", self.kind, self.text));
+            } else {
+                output.push_str(&format!("{:?}\n", self.kind));
+            }
+            for i in &self.children {
+                i.write_print(depth + 1, output);
+            }
+        }
+    }
+}
@@ -0,0 +1,680 @@
+//Module for turning interpreted documents into other formats:
+// A - The roles that the standard types play in a document, shared by every exporter
+// B - HTML
+// C - Markdown
+// D - LaTeX
+// E - JSON, of both the parser's output and interpreted documents
+// F - Anki flashcards
+// G - CSV and TSV, one file for each table
+
+//A - Define what each type means to an exporter.
+pub mod roles {
+    use crate::core::interpreter_structs::{DiazoObject, Field};
+
+    //How an object is laid out, whatever the format.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Role {
+        Heading,        //h
+        Term,           //def and sntx: a term and what it means.
+        List,           //Collections read as bullet points, such as ls, exp and aka. Also any other type that has elements.
+        Sequence,       //seq: a list whose order matters.
+        Definitions,    //lsdef: a list of terms.
+        Table,          //tabl
+        Text            //Raw text, and every other type that isn't a collection.
+    }
+
+    //Find the role of an object from its type. Types outside of the standard library are judged by whether they have elements.
+    pub fn role(object: &DiazoObject) -> Role {
+        match object.type_name.as_deref() {
+            Some("h") => Role::Heading,
+            Some("def") | Some("sntx") => Role::Term,
+            Some("seq") => Role::Sequence,
+            Some("lsdef") => Role::Definitions,
+            Some("tabl") => Role::Table,
+            Some("ls") | Some("exp") | Some("equ") | Some("aka") | Some("lstat") | Some("lstrip") => Role::List,
+            Some(_) if !object.elements.is_empty() => Role::List,
+            _ => Role::Text
+        }
+    }
+
+    //Every field of an object, label first.
+    pub fn fields(object: &DiazoObject) -> Vec<&Field> {
+        object.label.iter().chain(object.contents.iter()).collect()
+    }
+
+    //The header row of a table: a blank corner above the column of row titles, then the columns given in the header of the expression.
+    pub fn header(table: &DiazoObject) -> impl Iterator<Item = Option<&Field>> {
+        std::iter::once(None).chain(table.contents.iter().map(Some))
+    }
+
+    //The text of the first heading in a document, for formats that want a title.
+    pub fn title(objects: &[DiazoObject]) -> Option<String> {
+        objects.iter().find(|o| role(o) == Role::Heading).map(|o| fields(o).iter().map(|f| f.text()).collect::<Vec<_>>().join(" "))
+    }
+}
+
+//B - Semantic HTML, for publishing notes as web pages.
+pub mod html {
+    use crate::core::interpreter_structs::{DiazoObject, Field, Fragment};
+    use super::roles::{self, Role};
+
+    //Render a document as a standalone HTML page. The first h gives the page its title.
+    pub fn html(objects: &[DiazoObject]) -> String {
+        let title = roles::title(objects).unwrap_or_else(|| "diazo".to_string());
+        let mut output = String::new();
+        output.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        output.push_str(&format!("<title>{}</title>\n</head>\n<body>\n", escape(&title)));
+        for i in objects {
+            object(i, 0, &mut output);
+        }
+        output.push_str("</body>\n</html>\n");
+        output
+    }
+
+    //Render a single object. Depth is how far it is nested inside collections, since only top level collections get a heading of their own.
+    fn object(o: &DiazoObject, depth: usize, output: &mut String) {
+        let class = o.type_name.as_deref().unwrap_or("text");
+        match roles::role(o) {
+            Role::Heading => output.push_str(&format!("<h1>{}</h1>\n", join(&roles::fields(o), " "))),
+            Role::Term => {
+                output.push_str(&format!("<dl class=\"{}\">\n", class));
+                term(o, output);
+                output.push_str("</dl>\n");
+            },
+            Role::List | Role::Sequence => {
+                let tag = if roles::role(o) == Role::Sequence { "ol" } else { "ul" };
+                label(o, depth, output);
+                output.push_str(&format!("<{} class=\"{}\">\n", tag, class));
+                for i in &o.elements {
+                    output.push_str("<li>");
+                    if i.type_name.is_some() {  //A nested expression, such as an exp inside an exp.
+                        output.push('\n');
+                        object(i, depth + 1, output);
+                    } else {
+                        output.push_str(&join(&roles::fields(i), " — "));
+                    }
+                    output.push_str("</li>\n");
+                }
+                output.push_str(&format!("</{}>\n", tag));
+            },
+            Role::Definitions => {
+                label(o, depth, output);
+                output.push_str(&format!("<dl class=\"{}\">\n", class));
+                for i in &o.elements {
+                    term(i, output);
+                }
+                output.push_str("</dl>\n");
+            },
+            Role::Table => {
+                output.push_str(&format!("<table class=\"{}\">\n", class));
+                if let Some(l) = &o.label {
+                    output.push_str(&format!("<caption>{}</caption>\n", field(l)));
+                }
+                output.push_str("<thead>\n<tr>");
+                for i in roles::header(o) {
+                    match i {
+                        Some(f) => output.push_str(&format!("<th scope=\"col\">{}</th>", field(f))),
+                        None => output.push_str("<th></th>")
+                    }
+                }
+                output.push_str("</tr>\n</thead>\n<tbody>\n");
+                for i in &o.elements {
+                    let cells = roles::fields(i);
+                    output.push_str("<tr>");
+                    if let Some((title, rest)) = cells.split_first() {
+                        output.push_str(&format!("<th scope=\"row\">{}</th>", field(title)));
+                        for j in rest {
+                            output.push_str(&format!("<td>{}</td>", field(j)));
+                        }
+                    }
+                    output.push_str("</tr>\n");
+                }
+                output.push_str("</tbody>\n</table>\n");
+            },
+            Role::Text => {
+                if o.type_name.is_some() {
+                    output.push_str(&format!("<p class=\"{}\">", class));
+                } else {
+                    output.push_str("<p>");
+                }
+                match &o.label {
+                    Some(l) if !o.contents.is_empty() => output.push_str(&format!("<strong>{}</strong> {}", field(l), join(&o.contents.iter().collect::<Vec<_>>(), " — "))),
+                    _ => output.push_str(&join(&roles::fields(o), " — "))
+                }
+                output.push_str("</p>\n");
+                for i in &o.elements {  //Expressions nested under raw text.
+                    object(i, depth, output);
+                }
+            }
+        }
+    }
+
+    //The label of a collection, as a heading at the top level and as plain text inside a list item.
+    fn label(o: &DiazoObject, depth: usize, output: &mut String) {
+        if let Some(l) = &o.label {
+            if depth == 0 {
+                output.push_str(&format!("<h2>{}</h2>\n", field(l)));
+            } else {
+                output.push_str(&format!("{}\n", field(l)));
+            }
+        }
+    }
+
+    //A term and its definition, for the inside of a <dl>.
+    fn term(o: &DiazoObject, output: &mut String) {
+        let fields = roles::fields(o);
+        if let Some((t, rest)) = fields.split_first() {
+            output.push_str(&format!("<dt>{}</dt>\n<dd>{}</dd>\n", field(t), join(rest, " ")));
+        }
+    }
+
+    fn join(fields: &[&Field], separator: &str) -> String {
+        fields.iter().map(|f| field(f)).collect::<Vec<_>>().join(separator)
+    }
+
+    //Render a field, with its code and math blocks.
+    pub(super) fn field(f: &Field) -> String {
+        f.fragments.iter().map(|i| match i {
+            Fragment::Text(s) => escape(s),
+            Fragment::Code(s) => format!("<code>{}</code>", escape(s)),
+            Fragment::Math(s) => format!("<span class=\"math\">\\({}\\)</span>", escape(s)),  //Delimited for MathJax and KaTeX.
+            Fragment::Fenced { language: Some(l), code } => format!("<pre><code class=\"language-{}\">{}</code></pre>", escape(l), escape(code)),
+            Fragment::Fenced { language: None, code } => format!("<pre><code>{}</code></pre>", escape(code))
+        }).collect()
+    }
+
+    pub(super) fn escape(s: &str) -> String {
+        s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+    }
+}
+
+//C - CommonMark, with pipe tables and $ $ math as most wikis support them.
+pub mod markdown {
+    use crate::core::interpreter_structs::{DiazoObject, Field, Fragment};
+    use super::roles::{self, Role};
+
+    //Render a document as Markdown, one block per top level object.
+    pub fn markdown(objects: &[DiazoObject]) -> String {
+        let mut blocks: Vec<String> = Vec::new();
+        for i in objects {
+            block(i, &mut blocks);
+        }
+        let mut output = blocks.join("\n\n");
+        output.push('\n');
+        output
+    }
+
+    //Render a top level object as one or more blocks.
+    fn block(o: &DiazoObject, blocks: &mut Vec<String>) {
+        match roles::role(o) {
+            Role::Heading => blocks.push(format!("# {}", line(o))),
+            Role::List | Role::Sequence | Role::Definitions => {
+                if let Some(l) = &o.label {
+                    blocks.push(format!("## {}", field(l)));
+                }
+                if !o.elements.is_empty() {  //An empty collection writes only its label, rather than an empty block.
+                    let mut output = String::new();
+                    items(o, 0, &mut output);
+                    blocks.push(output.trim_end().to_string());
+                }
+            },
+            Role::Table => {
+                if let Some(l) = &o.label {
+                    blocks.push(format!("## {}", field(l)));
+                }
+                if o.elements.is_empty() {  //Likewise for a table without rows.
+                    return;
+                }
+                let mut output = String::from("|");
+                for i in roles::header(o) {
+                    output.push_str(&format!(" {} |", i.map_or(String::new(), cell)));
+                }
+                output.push_str(&format!("\n|{}", " --- |".repeat(o.contents.len() + 1)));
+                for i in &o.elements {
+                    output.push_str("\n|");
+                    for j in roles::fields(i) {
+                        output.push_str(&format!(" {} |", cell(j)));
+                    }
+                }
+                blocks.push(output);
+            },
+            Role::Term | Role::Text => {
+                blocks.push(line(o));
+                for i in &o.elements {  //Expressions nested under raw text.
+                    block(i, blocks);
+                }
+            }
+        }
+    }
+
+    //Render the elements of a collection as list items, following the nesting of the elements.
+    fn items(o: &DiazoObject, indent: usize, output: &mut String) {
+        let marker = if roles::role(o) == Role::Sequence { "1." } else { "-" };
+        for i in &o.elements {
+            output.push_str(&format!("{}{} {}\n", " ".repeat(indent), marker, line(i)));
+            if i.type_name.is_some() && !i.elements.is_empty() {
+                items(i, indent + marker.len() + 1, output);   //Nested items line up with the text of the item above.
+            }
+        }
+    }
+
+    //Render an object as a single line: a term in bold before its definition, a collection as its label, and anything else as its fields.
+    fn line(o: &DiazoObject) -> String {
+        let fields = roles::fields(o);
+        match roles::role(o) {
+            _ if o.type_name.is_none() => join(&fields, " — "),
+            Role::Term => match fields.split_first() {
+                Some((t, rest)) => format!("**{}**: {}", field(t), join(rest, " ")),
+                None => String::new()
+            },
+            Role::List | Role::Sequence | Role::Definitions | Role::Table if o.label.is_some() => field(o.label.as_ref().unwrap()),
+            _ => match &o.label {
+                Some(l) if !o.contents.is_empty() => format!("**{}** {}", field(l), join(&o.contents.iter().collect::<Vec<_>>(), " — ")),
+                _ => join(&fields, " — ")
+            }
+        }
+    }
+
+    fn join(fields: &[&Field], separator: &str) -> String {
+        fields.iter().map(|f| field(f)).collect::<Vec<_>>().join(separator)
+    }
+
+    //Render a field, with its code and math blocks as inline spans. Fenced code blocks stay fenced, with a fence longer than any run of backticks inside them.
+    fn field(f: &Field) -> String {
+        f.fragments.iter().map(fragment).collect()
+    }
+
+    //Render a field in a cell of a table, where a | ends the cell even inside code or math. Text has it escaped already.
+    fn cell(f: &Field) -> String {
+        f.fragments.iter().map(|i| match i {
+            Fragment::Text(_) => fragment(i),
+            _ => fragment(i).replace('|', "\\|")
+        }).collect()
+    }
+
+    fn fragment(i: &Fragment) -> String {
+        match i {
+            Fragment::Text(s) => escape(s),
+            Fragment::Code(s) if s.contains('`') => format!("`` {} ``", s),
+            Fragment::Code(s) => format!("`{}`", s),
+            Fragment::Math(s) => format!("${}$", s),
+            Fragment::Fenced { language, code } => {
+                let longest = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+                let fence = "`".repeat(longest.max(2) + 1);
+                format!("\n{}{}\n{}\n{}\n", fence, language.as_deref().unwrap_or(""), code, fence)
+            }
+        }
+    }
+
+    //Escape the characters that Markdown would otherwise read as formatting.
+    fn escape(s: &str) -> String {
+        let mut output = String::new();
+        for c in s.chars() {
+            if "\\`*_[]<>|$#".contains(c) {
+                output.push('\\');
+            }
+            output.push(c);
+        }
+        output
+    }
+}
+
+//D - LaTeX, for printable handouts.
+pub mod latex {
+    use crate::core::interpreter_structs::{DiazoObject, Field, Fragment};
+    use super::roles::{self, Role};
+
+    //Render a document as a standalone LaTeX article.
+    pub fn latex(objects: &[DiazoObject]) -> String {
+        let mut output = String::from("\\documentclass{article}\n\\usepackage[utf8]{inputenc}\n\\usepackage{amsmath}\n\n\\begin{document}\n\n");
+        for i in objects {
+            object(i, 0, &mut output);
+        }
+        output.push_str("\\end{document}\n");
+        output
+    }
+
+    //Render a single object. Depth is how far it is nested inside collections, since only top level collections get a heading of their own.
+    fn object(o: &DiazoObject, depth: usize, output: &mut String) {
+        match roles::role(o) {
+            Role::Heading => output.push_str(&format!("\\section{{{}}}\n\n", join(&roles::fields(o), " "))),
+            Role::Term => {
+                output.push_str("\\begin{description}\n");
+                term(o, output);
+                output.push_str("\\end{description}\n\n");
+            },
+            Role::List | Role::Sequence => {
+                let environment = if roles::role(o) == Role::Sequence { "enumerate" } else { "itemize" };
+                label(o, depth, output);
+                if o.elements.is_empty() {  //LaTeX won't accept an empty list.
+                    return
+                }
+                output.push_str(&format!("\\begin{{{}}}\n", environment));
+                for i in &o.elements {
+                    output.push_str("\\item ");
+                    if i.type_name.is_some() {  //A nested expression, such as an exp inside an exp.
+                        object(i, depth + 1, output);
+                    } else {
+                        output.push_str(&join(&roles::fields(i), " --- "));
+                        output.push('\n');
+                    }
+                }
+                output.push_str(&format!("\\end{{{}}}\n\n", environment));
+            },
+            Role::Definitions => {
+                label(o, depth, output);
+                if o.elements.is_empty() {
+                    return
+                }
+                output.push_str("\\begin{description}\n");
+                for i in &o.elements {
+                    term(i, output);
+                }
+                output.push_str("\\end{description}\n\n");
+            },
+            Role::Table => {
+                label(o, depth, output);
+                output.push_str(&format!("\\begin{{tabular}}{{l|{}}}\n", "l".repeat(o.contents.len())));
+                let header: Vec<String> = roles::header(o).map(|i| i.map_or(String::new(), inline)).collect();
+                output.push_str(&format!("{} \\\\\n\\hline\n", header.join(" & ")));
+                for i in &o.elements {
+                    output.push_str(&format!("{} \\\\\n", roles::fields(i).iter().map(|f| inline(f)).collect::<Vec<_>>().join(" & ")));
+                }
+                output.push_str("\\end{tabular}\n\n");
+            },
+            Role::Text => {
+                match &o.label {
+                    Some(l) if !o.contents.is_empty() => output.push_str(&format!("\\textbf{{{}}} {}", field(l), join(&o.contents.iter().collect::<Vec<_>>(), " --- "))),
+                    _ => output.push_str(&join(&roles::fields(o), " --- "))
+                }
+                output.push_str(if depth == 0 { "\n\n" } else { "\n" });
+                for i in &o.elements {  //Expressions nested under raw text.
+                    object(i, depth, output);
+                }
+            }
+        }
+    }
+
+    //The label of a collection, as an unnumbered subsection at the top level and as the text of the item inside a list.
+    fn label(o: &DiazoObject, depth: usize, output: &mut String) {
+        match &o.label {
+            Some(l) if depth == 0 => output.push_str(&format!("\\subsection*{{{}}}\n\n", field(l))),
+            Some(l) => output.push_str(&format!("{}\n", field(l))),
+            None if depth > 0 => output.push('\n'),
+            None => ()
+        }
+    }
+
+    //A term and its definition, for the inside of a description.
+    fn term(o: &DiazoObject, output: &mut String) {
+        let fields = roles::fields(o);
+        if let Some((t, rest)) = fields.split_first() {
+            output.push_str(&format!("\\item[{{{}}}] {}\n", inline(t), join(rest, " ")));
+        }
+    }
+
+    fn join(fields: &[&Field], separator: &str) -> String {
+        fields.iter().map(|f| field(f)).collect::<Vec<_>>().join(separator)
+    }
+
+    //Render a field. Math blocks are written as inline math untouched, code blocks in a typewriter font and fenced blocks verbatim.
+    fn field(f: &Field) -> String {
+        f.fragments.iter().map(|i| match i {
+            Fragment::Text(s) => escape(s),
+            Fragment::Code(s) => format!("\\texttt{{{}}}", escape(s)),
+            Fragment::Math(s) => format!("${}$", s),
+            Fragment::Fenced { code, .. } => format!("\n\\begin{{verbatim}}\n{}\n\\end{{verbatim}}\n", code)
+        }).collect()
+    }
+
+    //Render a field where verbatim can't go, such as a description's term or a table cell. Fenced blocks are written in a typewriter font instead, on one line.
+    fn inline(f: &Field) -> String {
+        f.fragments.iter().map(|i| match i {
+            Fragment::Fenced { code, .. } => format!("\\texttt{{{}}}", escape(&code.replace('\n', " "))),
+            other => field(&Field { fragments: vec![other.clone()] })
+        }).collect()
+    }
+
+    //Escape the characters that LaTeX treats as commands.
+    fn escape(s: &str) -> String {
+        let mut output = String::new();
+        for c in s.chars() {
+            match c {
+                '\\' => output.push_str("\\textbackslash{}"),
+                '~' => output.push_str("\\textasciitilde{}"),
+                '^' => output.push_str("\\textasciicircum{}"),
+                '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                    output.push('\\');
+                    output.push(c);
+                },
+                c => output.push(c)
+            }
+        }
+        output
+    }
+}
+
+//E - JSON, for tools written in other languages. Written by hand, since the crate has no dependencies.
+/* Schema, version 1. Every document is a single object holding the version it was written with.
+
+    Parser output:          {"version": 1, "statements": [Statement, ...]}
+        Statement:          {"kind": "declaration" | "expression" | "raw_text", "tree": Node}
+        Node:               {"token": Token, "span": Span, "children": [Node, ...]}
+        Span:               {"file": number | null, "offset": number, "line": number, "column": number}
+                            file is the index of an imported file in the order they were imported, or null for the file itself.
+                            line and column count from 1, offset from 0 in bytes. A line of 0 means the token wasn't read from any line.
+        Token:              {"type": name} for tokens without data, such as {"type": "Element"},
+                            {"type": name, "value": string} for tokens holding text, such as {"type": "TypeInstance", "value": "def"},
+                            {"type": "ContentWithFormatting", "value": [Token, ...]}
+                            The names are those of the variants of core::tokens::Tokens.
+
+    Interpreted document:   {"version": 1, "objects": [Object, ...]}
+        Object:             {"type": string | null, "label": Field | null, "contents": [Field, ...], "elements": [Object, ...]}
+                            type is null for raw text and for elements that aren't a declared type, such as the rows of a tabl.
+        Field:              [Fragment, ...]
+        Fragment:           {"kind": "text" | "code" | "math", "text": string}, or
+                            {"kind": "fenced", "language": string | null, "text": string} for fenced code blocks, whose text keeps its line breaks.
+*/
+pub mod json {
+    use crate::core::tokens::Tokens;
+    use crate::core::parser_structs::{IrElements, Node};
+    use crate::core::interpreter_structs::{DiazoObject, Field, Fragment};
+
+    pub const VERSION: usize = 1;   //Raised whenever the schema above changes in a way that could break a reader.
+
+    //Write out the parser's output.
+    pub fn ast(input: &[IrElements]) -> String {
+        let statements: Vec<String> = input.iter().map(|i| {
+            let (kind, tree) = match i {
+                IrElements::TypeDeclaration(t) => ("declaration", t),
+                IrElements::TypeExpression(t) => ("expression", t),
+                IrElements::RawText(t) => ("raw_text", t)
+            };
+            format!("{{\"kind\":{},\"tree\":{}}}", string(kind), node(tree.node(tree.root())))
+        }).collect();
+        format!("{{\"version\":{},\"statements\":[{}]}}\n", VERSION, statements.join(","))
+    }
+
+    //Write out an interpreted document.
+    pub fn objects(input: &[DiazoObject]) -> String {
+        format!("{{\"version\":{},\"objects\":[{}]}}\n", VERSION, input.iter().map(object).collect::<Vec<_>>().join(","))
+    }
+
+    fn node(n: Node) -> String {
+        let s = n.span();
+        let span = format!("{{\"file\":{},\"offset\":{},\"line\":{},\"column\":{}}}", s.file.map_or("null".to_string(), |f| f.to_string()), s.offset, s.line, s.column);
+        format!("{{\"token\":{},\"span\":{},\"children\":[{}]}}", token(n.value()), span, n.children().map(node).collect::<Vec<_>>().join(","))
+    }
+
+    fn token(t: &Tokens) -> String {
+        let value = match t {
+            Tokens::Separator(s) | Tokens::Filename(s) | Tokens::CommentContents(s) | Tokens::TypeName(s) | Tokens::E(s) | Tokens::C(s)
+            | Tokens::TypeAsDeclarationParameter(s) | Tokens::Annotation(s) | Tokens::TypeInstance(s) | Tokens::Content(s) | Tokens::MathBlock(s) | Tokens::CodeBlock(s) | Tokens::CodeFence(s) => string(s),
+            Tokens::ContentWithFormatting(v) => format!("[{}]", v.iter().map(token).collect::<Vec<_>>().join(",")),
+            _ => return format!("{{\"type\":{}}}", string(t.name()))
+        };
+        format!("{{\"type\":{},\"value\":{}}}", string(t.name()), value)
+    }
+
+    fn object(o: &DiazoObject) -> String {
+        format!("{{\"type\":{},\"label\":{},\"contents\":[{}],\"elements\":[{}]}}",
+            o.type_name.as_deref().map_or("null".to_string(), string),
+            o.label.as_ref().map_or("null".to_string(), field),
+            o.contents.iter().map(field).collect::<Vec<_>>().join(","),
+            o.elements.iter().map(object).collect::<Vec<_>>().join(","))
+    }
+
+    fn field(f: &Field) -> String {
+        format!("[{}]", f.fragments.iter().map(|i| {
+            let kind = match i {
+                Fragment::Text(_) => "text",
+                Fragment::Code(_) => "code",
+                Fragment::Math(_) => "math",
+                Fragment::Fenced { language, .. } => {
+                    return format!("{{\"kind\":\"fenced\",\"language\":{},\"text\":{}}}", language.as_deref().map_or("null".to_string(), string), string(i.text()))
+                }
+            };
+            format!("{{\"kind\":{},\"text\":{}}}", string(kind), string(i.text()))
+        }).collect::<Vec<_>>().join(","))
+    }
+
+    //A JSON string literal.
+    pub(crate) fn string(s: &str) -> String {
+        let mut output = String::from("\"");
+        for c in s.chars() {
+            match c {
+                '"' => output.push_str("\\\""),
+                '\\' => output.push_str("\\\\"),
+                '\n' => output.push_str("\\n"),
+                '\r' => output.push_str("\\r"),
+                '\t' => output.push_str("\\t"),
+                c if (c as u32) < 0x20 => output.push_str(&format!("\\u{:04x}", c as u32)),
+                c => output.push(c)
+            }
+        }
+        output.push('"');
+        output
+    }
+}
+
+//F - Anki flashcards, as a tab separated file that Anki can import directly.
+//Cards are made from every type whose declaration carries the @card annotation. Their first field is the front of the card and the rest is the back.
+pub mod anki {
+    use crate::core::interpreter_structs::{DiazoObject, TypeRegistry};
+    use super::roles::{self, Role};
+    use super::html;
+
+    pub const CARD: &str = "card";  //The annotation that marks a type as making flashcards.
+    pub const DEFAULT_DECK: &str = "diazo";     //The deck for cards that come before any h.
+
+    //Write out the cards of a document. The types are needed to know which of them are marked for cards, see interpreter::registry.
+    //Each card goes in the deck named by the h above it, and is tagged with the names of its type and the collections it sits in.
+    pub fn anki(objects: &[DiazoObject], types: &TypeRegistry) -> String {
+        let mut output = String::from("#separator:tab\n#html:true\n#columns:Front\tBack\tDeck\tTags\n#deck column:3\n#tags column:4\n");
+        let mut deck = DEFAULT_DECK.to_string();
+        for i in objects {
+            if roles::role(i) == Role::Heading {
+                deck = roles::fields(i).iter().map(|f| f.text()).collect::<Vec<_>>().join(" ");
+                continue
+            }
+            cards(i, &mut Vec::new(), &deck, types, &mut output);
+        }
+        output
+    }
+
+    //Write the cards of an object and everything nested in it. tags holds the types of the collections it sits in.
+    fn cards<'a>(o: &'a DiazoObject, tags: &mut Vec<&'a str>, deck: &str, types: &TypeRegistry, output: &mut String) {
+        let marked = o.type_name.as_deref().and_then(|t| types.get(t)).is_some_and(|t| t.annotated(CARD));
+        if let Some(t) = &o.type_name {
+            tags.push(t);
+        }
+        if marked {
+            card(o, tags, deck, output);
+        }
+        for i in &o.elements {
+            if marked && i.type_name.is_none() {    //The plain elements of a collection marked for cards are cards too.
+                card(i, tags, deck, output);
+            } else {
+                cards(i, tags, deck, types, output);
+            }
+        }
+        if o.type_name.is_some() {
+            tags.pop();
+        }
+    }
+
+    fn card(o: &DiazoObject, tags: &[&str], deck: &str, output: &mut String) {
+        let fields = roles::fields(o);
+        if let Some((front, back)) = fields.split_first() {
+            if back.is_empty() {    //Nothing to put on the back, so there is no card to make.
+                return
+            }
+            let mut tags: Vec<String> = tags.iter().map(|t| t.replace(' ', "_")).collect();
+            tags.dedup();
+            let back: Vec<String> = back.iter().map(|f| html::field(f)).collect();
+            output.push_str(&[html::field(front), back.join(" — "), deck.to_string(), tags.join(" ")].map(|c| cell(&c)).join("\t"));
+            output.push('\n');
+        }
+    }
+
+    //Tabs and line breaks would start a new column or card, so they become spaces.
+    fn cell(s: &str) -> String {
+        s.replace(['\t', '\n', '\r'], " ")
+    }
+}
+
+//G - CSV and TSV files of tables, for spreadsheets. Every table in a document gets a file of its own, named after its label.
+pub mod csv {
+    use crate::core::interpreter_structs::DiazoObject;
+    use super::roles::{self, Role};
+
+    //A table written out as a file.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct TableFile {
+        pub name: String,       //File name made from the label of the table, e.g. "cell_walls.csv".
+        pub contents: String
+    }
+
+    //Write out every table in a document, including those nested in other objects. Use ',' as the delimiter for CSV and '\t' for TSV.
+    pub fn tables(objects: &[DiazoObject], delimiter: char) -> Vec<TableFile> {
+        let extension = if delimiter == '\t' { "tsv" } else { "csv" };
+        let mut output: Vec<TableFile> = Vec::new();
+        for table in objects.iter().flat_map(|o| o.iter()).filter(|o| roles::role(o) == Role::Table) {
+            let mut contents = String::new();
+            row(roles::header(table).map(|i| i.map_or(String::new(), |f| f.text())), delimiter, &mut contents);
+            for i in &table.elements {
+                row(roles::fields(i).iter().map(|f| f.text()), delimiter, &mut contents);
+            }
+            //Name the file after the label, keeping names unique when two tables share a label.
+            let stem = table.label.as_ref().map(|l| file_stem(&l.text())).filter(|s| !s.is_empty()).unwrap_or_else(|| "table".to_string());
+            let mut name = format!("{}.{}", stem, extension);
+            let mut count = 1;
+            while output.iter().any(|t| t.name == name) {
+                count += 1;
+                name = format!("{}_{}.{}", stem, count, extension);
+            }
+            output.push(TableFile { name, contents });
+        }
+        output
+    }
+
+    fn row(cells: impl Iterator<Item = String>, delimiter: char, output: &mut String) {
+        let cells: Vec<String> = cells.map(|c| cell(&c, delimiter)).collect();
+        output.push_str(&cells.join(&delimiter.to_string()));
+        output.push_str("\r\n");  //Line endings as RFC 4180 asks for.
+    }
+
+    //Quote a cell if it holds anything that would otherwise end it early.
+    fn cell(s: &str, delimiter: char) -> String {
+        if s.contains(delimiter) || s.contains(['"', '\n', '\r']) {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s.to_string()
+        }
+    }
+
+    //A file name made from a label, with anything besides letters, digits, - and _ turned into _.
+    fn file_stem(label: &str) -> String {
+        label.trim().chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect()
+    }
+}
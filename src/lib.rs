@@ -1,25 +1,294 @@
-mod core; //Module which stores key information such as the type system, tokens, etc.
+pub mod core; //Module which stores key information such as the type system, tokens, etc.
+pub mod exporters; //Module which renders interpreted documents in other formats.
+pub mod lsp; //Module which serves editors over the Language Server Protocol.
 
 //Module containing procedures for filehandling, which will be improved over time.
 pub mod filehandling {
     use std::fs;
+    use std::env;
     use std::io::Error as ioError;
+    use std::path::{Path, PathBuf};
+
+    pub const SEARCH_PATH_VAR: &str = "DIAZO_PATH";    //Environment variable holding extra directories for *use to look in, separated like PATH.
+    pub const STD: &str = "std";                        //Name that *use gives the standard library, which is built in rather than read from disk.
+    pub const PRELUDE: &str = include_str!("../prelude.dz");   //Source of the standard library: l, h, def, exp, tabl and the other standard types.
 
     pub fn read_file(file: &str) -> Result<String, ioError> {
-        match fs::read_to_string(file) {
-            Ok(contents) => Ok(contents),
-            Err(e) => Err(e)
+        fs::read_to_string(file)
+    }
+
+    //The search path set in the environment, if any.
+    pub fn search_path() -> Vec<PathBuf> {
+        match env::var_os(SEARCH_PATH_VAR) {
+            Some(paths) => env::split_paths(&paths).filter(|p| !p.as_os_str().is_empty()).collect(),
+            None => Vec::new()
         }
     }
+
+    //Find the file named by a *use. Absolute paths are taken as they are, otherwise the importing file's directory is tried first, then each directory in the search path.
+    pub fn resolve(name: &str, dir: &Path, search_path: &[PathBuf]) -> Option<PathBuf> {
+        let name = Path::new(name);
+        let found = if name.is_absolute() {
+            Some(name.to_path_buf()).filter(|p| p.is_file())?
+        } else {
+            std::iter::once(dir).chain(search_path.iter().map(|p| p.as_path())).map(|d| d.join(name)).find(|p| p.is_file())?
+        };
+        Some(fs::canonicalize(&found).unwrap_or(found))    //Canonical paths, so that a file reached two ways is still recognized as the same file.
+    }
 }
 
 //Module containing the lexer, a component designed to parse text into tokens that can then be analyzed.
 pub mod lexer {
-    use crate::core::tokens;
+    use crate::core::{tokens, errors, imports};
+    use crate::filehandling;
     use std::fs;
+    use std::path::{Path, PathBuf};
         
+    //Settings for a run of the lexer.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct LexerConfig {
+        pub source: Option<String>,         //The file being lexed, if it came from one. Errors are located in it and its imports are looked up next to it.
+        pub allow_imports: bool,            //Whether *use may bring in other files.
+        pub types: Vec<String>,             //Types that are known before the input is read.
+        pub search_path: Vec<PathBuf>,      //Directories that *use looks in when a file isn't next to the one importing it.
+        pub prelude: bool,                  //Whether the standard library is imported before the input, as if it began with *use std.
+        pub recover: bool,                  //Whether lines with syntax errors are left out so that lexing can carry on, instead of stopping at the first error.
+        pub declarations_only: bool,        //Whether only type declarations are kept in the output, as when a file is imported.
+        pub indent_spaces: Option<usize>    //How many spaces make up a level of indentation, or None if only tabs do. An *indent pragma at the top of a file overrides this.
+    }
+
+    impl Default for LexerConfig {
+        //Imports are looked up from the working directory, then the search path in the environment.
+        fn default() -> LexerConfig {
+            LexerConfig { source: None, allow_imports: true, types: Vec::new(), search_path: filehandling::search_path(), prelude: false, recover: false, declarations_only: false, indent_spaces: None }
+        }
+    }
+
+    impl LexerConfig {
+        //Settings for lexing a file on disk.
+        pub fn file(path: &str) -> LexerConfig {
+            LexerConfig { source: Some(path.to_string()), ..LexerConfig::default() }
+        }
+    }
+
     //The lexer, a function which converts the text String into tokens, stored in order as a Vector of enum variants
-    pub fn lexer(input: String) -> Result<Vec<tokens::Tokens>, &'static str> {
+    pub fn lexer(input: String) -> Result<Vec<tokens::Token>, errors::DiazoError> {
+        let (output, _, _) = lexer_with(input, &LexerConfig::default())?;
+        Ok(output)
+    }
+
+    //The lexer in recovery mode. Lines with syntax errors are left out of the output and the lexer carries on, so that every error in the input is returned at once.
+    pub fn lexer_recovering(input: String) -> (Vec<tokens::Token>, Vec<errors::DiazoError>) {
+        let (output, error_list, _) = lexer_recovering_with(input, &LexerConfig::default());
+        (output, error_list)
+    }
+
+    //The lexer in recovery mode with the given settings, also returning the graph of every file brought in by *use.
+    pub fn lexer_recovering_with(input: String, config: &LexerConfig) -> (Vec<tokens::Token>, Vec<errors::DiazoError>, imports::ImportGraph) {
+        match lexer_with(input, &LexerConfig { recover: true, ..config.clone() }) {
+            Ok(r) => r,
+            Err(e) => (Vec::new(), vec![e], imports::ImportGraph::new())   //Only reachable outside of recovery mode.
+        }
+    }
+
+    //The lexer with the given settings, also returning the errors recovered from and the graph of every file brought in by *use.
+    pub fn lexer_with(input: String, config: &LexerConfig) -> Result<(Vec<tokens::Token>, Vec<errors::DiazoError>, imports::ImportGraph), errors::DiazoError> {
+        let mut graph = imports::ImportGraph::new();
+        let (output, _, error_list) = lex(&input, config, None, &mut graph)?;
+        Ok((output, error_list, graph))
+    }
+
+    //Import a file for *use: lex it, then hand its types to the importing file and return its type declarations.
+    //A file that has been imported before is not lexed again, its declarations are already in the output so only its types are handed over.
+    fn import_file(name: &str, importer: Option<usize>, config: &LexerConfig, location: errors::Location, typelist_to_edit: &mut Vec<String>, graph: &mut imports::ImportGraph) -> Result<Vec<tokens::Token>, errors::DiazoError> {
+        //Find the file next to the one importing it, or else in the search path. The standard library is never looked for on disk.
+        let dir = match config.source.as_deref().and_then(|f| Path::new(f).parent()) {
+            Some(dir) => dir.to_path_buf(),
+            None => PathBuf::new()  //Relative to the working directory.
+        };
+        let path = match if name == filehandling::STD { Some(name.into()) } else { filehandling::resolve(name, &dir, &config.search_path) } {
+            Some(p) => p.to_string_lossy().into_owned(),
+            None => return Err(errors::DiazoError::Io { kind: errors::ErrorKind::Import, file: name.to_string(),
+                message: format!("No such file in {} or the search path ({}).", if dir.as_os_str().is_empty() { "the working directory".to_string() } else { dir.display().to_string() },
+                    config.search_path.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")) })
+        };
+        let filename = path.as_str();
+        if let Some(index) = graph.index(filename) {
+            if graph.chain.contains(&index) {   //The file is still being lexed further up, so importing it again would never finish.
+                let mut cycle: Vec<&str> = graph.chain.iter().skip_while(|i| **i != index).map(|i| graph.files[*i].as_str()).collect();
+                cycle.push(filename);
+                return Err(errors::DiazoError::Lexer { kind: errors::ErrorKind::ImportCycle, location, token: filename.to_string(),
+                    message: format!("Invalid import: these files import each other in a cycle: {}", cycle.join(" -> ")) })
+            }
+            graph.edges.push((importer, index));
+            typelist_to_edit.extend(graph.types[index].iter().cloned());
+            return Ok(Vec::new())
+        }
+        //Find a file and read it, or else report that an issue has occurred.
+        let s = match if filename == filehandling::STD { Ok(filehandling::PRELUDE.to_string()) } else { fs::read_to_string(filename) } {
+            Ok(s) => s,
+            Err(e) => return Err(errors::DiazoError::Io { kind: errors::ErrorKind::Import, file: filename.to_string(), message: e.to_string() })
+        };
+        let index = graph.add(filename);
+        graph.edges.push((importer, index));
+        //Lex the file with the same lexer. Imported files are always lexed strictly, and only their declarations are kept.
+        let imported = LexerConfig { source: Some(path.clone()), allow_imports: true, types: Vec::new(), search_path: config.search_path.clone(), prelude: false, recover: false, declarations_only: true, indent_spaces: config.indent_spaces };
+        graph.chain.push(index);
+        let result = lex(&s, &imported, Some(index), graph);
+        graph.chain.pop();
+        let (temp1, temp2, _) = result?;
+        graph.types[index] = temp2.clone();
+        //A copy of the standard library, as older files bring in with *use prelude.dz, declares nothing that std hasn't already. Its declarations would only clash with those.
+        let copy_of_std = graph.index(filehandling::STD).is_some_and(|std| std != index && !temp2.is_empty() && temp2.iter().all(|t| graph.types[std].contains(t)));
+        typelist_to_edit.extend(temp2);
+        Ok(if copy_of_std { Vec::new() } else { temp1 })
+    }
+
+    //Keep only the type declarations of a lexed file, without any comments on their lines.
+    fn declarations(input: Vec<tokens::Token>) -> Vec<tokens::Token> {
+        let mut output: Vec<tokens::Token> = Vec::new();
+        for line in input.split_inclusive(|t| t.value == tokens::Tokens::Linebreak) {
+            if line.first().map(|t| &t.value) == Some(&tokens::Tokens::TypeKeyword) {
+                output.extend(line.iter().filter(|t| !matches!(t.value, tokens::Tokens::CommentLine | tokens::Tokens::CommentOpen | tokens::Tokens::CommentContents(_))).cloned());
+            }
+        }
+        if output.last().map(|t| &t.value) == Some(&tokens::Tokens::Linebreak) {
+            output.pop();   //The importing file adds the line break after its *use line itself.
+        }
+        output
+    }
+
+    //Where each word of a line starts, as (byte offset, column). Words are counted as the lexer splits lines: whitespace separates them, and each tab is a word of its own.
+    fn word_starts(line: &str) -> Vec<(usize, usize)> {
+        let mut output: Vec<(usize, usize)> = Vec::new();
+        let mut in_word: bool = false;
+        for (column, (offset, c)) in line.char_indices().enumerate() {
+            if c == '\t' || (!c.is_whitespace() && !in_word) {
+                output.push((offset, column + 1));
+            }
+            in_word = !c.is_whitespace();
+        }
+        output
+    }
+
+    //Find the *indent pragma at the top of a file, if there is one. Only blank lines and line comments may come before it.
+    //Returns the line it is on and what it sets: None for tabs alone, or the number of spaces that make up a level.
+    pub fn pragma(input: &str) -> Option<(usize, Result<Option<usize>, &'static str>)> {
+        for (number, line) in input.lines().enumerate() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                None | Some("//") => continue,
+                Some("*indent") => {
+                    let setting = match words.take_while(|w| *w != "//").collect::<Vec<_>>().as_slice() {
+                        ["tabs"] => Ok(None),
+                        [n] if n.parse::<usize>().is_ok_and(|n| n > 0) => Ok(n.parse().ok()),
+                        _ => Err("Invalid syntax: *indent should be followed by either \"tabs\" or the number of spaces that make up a level of indentation, e.g. *indent 4.")
+                    };
+                    return Some((number + 1, setting))
+                },
+                Some(_) => return None
+            }
+        }
+        None
+    }
+
+    //Where each level of indentation starts in the whitespace at the start of a line. Every tab is a level, and so is every run of the given number of spaces if spaces are allowed.
+    fn indent_levels(indent: &str, spaces: Option<usize>) -> Vec<usize> {
+        let mut output: Vec<usize> = Vec::new();
+        let mut run: usize = 0;     //Spaces since the last level.
+        for (offset, c) in indent.char_indices() {
+            if c == '\t' {
+                output.push(offset);
+                run = 0;
+            } else if let Some(n) = spaces {
+                run += 1;
+                if run == n {
+                    output.push(offset + 1 - n);
+                    run = 0;
+                }
+            }
+        }
+        output
+    }
+
+    //Check the whitespace at the start of a line, which has to be tabs or, if they are allowed, whole levels of spaces.
+    fn indent_error(indent: &str, spaces: Option<usize>) -> Option<(errors::ErrorKind, String)> {
+        let count = indent.matches(' ').count();
+        if indent.contains('\t') && count > 0 {
+            return Some((errors::ErrorKind::MixedIndentation, "Invalid indentation: This line is indented with both tabs and spaces. Use only one of them, since how far a tab reaches depends on the editor.".to_string()))
+        }
+        match spaces {
+            Some(n) if !count.is_multiple_of(n) => Some((errors::ErrorKind::UnevenIndentation,
+                format!("Invalid indentation: This line is indented by {} spaces, which isn't a whole number of levels of {} spaces.", count, n))),
+            _ => None
+        }
+    }
+
+    //Hide the escaped symbols in a word behind characters from Unicode's private use area, so that nothing looking for the symbols finds them.
+    //Pairs of backslashes in front of a symbol each become a single backslash, and an odd one out escapes the symbol.
+    //Characters already written in the range of the placeholders are marked, so they come back out as themselves.
+    fn escape(word: &str) -> String {
+        let symbols = tokens::escapable_list();
+        let mut output = String::new();
+        let mut rest = word;
+        let shield = |s: &str, output: &mut String| for c in s.chars() {
+            if (placeholder(0)..=marker()).contains(&c) {
+                output.push(marker());
+            }
+            output.push(c);
+        };
+        while let Some(at) = rest.find('\\') {
+            shield(&rest[..at], &mut output);
+            let after = rest[at..].trim_start_matches('\\');
+            let run = rest.len() - at - after.len();   //How many backslashes are in a row here.
+            match symbols.iter().enumerate().filter(|(_, s)| after.starts_with(**s)).max_by_key(|(_, s)| s.len()) {
+                Some((i, s)) => {
+                    output.push_str(&"\\".repeat(run / 2));
+                    if run % 2 == 1 {
+                        output.push(placeholder(i));
+                        rest = &after[s.len()..];
+                    } else {
+                        rest = after;
+                    }
+                },
+                None => {   //Backslashes before anything else are left alone.
+                    output.push_str(&rest[at..at + run]);
+                    rest = after;
+                }
+            }
+        }
+        shield(rest, &mut output);
+        output
+    }
+
+    //Put the escaped symbols back in, now without their backslashes, along with the marked characters that were already there.
+    fn unescape(text: &str) -> String {
+        let symbols = tokens::escapable_list();
+        let mut output = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match symbols.get((c as usize).wrapping_sub(placeholder(0) as usize)) {
+                _ if c == marker() => output.extend(chars.next()),
+                Some(s) => output.push_str(s),
+                None => output.push(c)
+            }
+        }
+        output
+    }
+
+    fn placeholder(i: usize) -> char {
+        char::from_u32(0xE000 + i as u32).unwrap()
+    }
+
+    //The character after the last placeholder, put in front of a character of the placeholders' range written in the input.
+    fn marker() -> char {
+        placeholder(tokens::escapable_list().len())
+    }
+
+    type Lexed = (Vec<tokens::Token>, Vec<String>, Vec<errors::DiazoError>);  //Output, every type known by the end, and the errors recovered from.
+
+    //The lexer itself. importer is the file's index in the import graph, or None for the input handed to the lexer.
+    fn lex(input: &str, config: &LexerConfig, importer: Option<usize>, graph: &mut imports::ImportGraph) -> Result<Lexed, errors::DiazoError> {
 
         //Declarations for the lexer's operation.
         let mut scope_stack: Vec<tokens::Tokens> = Vec::new();  //Declare a vector functioning as a stack for handling scope. The type of token in the stack determines the head's reading mode.
@@ -32,70 +301,114 @@ pub mod lexer {
             Comments, // The head is reading comments and ignoring them
         }
         let mut mode: Mode;                                     //Declare a variable to store the current mode.
-        let mut types: Vec<String> = Vec::new();                //Initialize a vector to store the types that the lexer can recognize.
+        let mut types: Vec<String> = config.types.clone();      //Initialize a vector to store the types that the lexer can recognize.
         let mut output:Vec<tokens::Tokens> = Vec::new();        //Initialize output vector.
+        let mut spans: Vec<tokens::Span> = Vec::new();          //Where each token in the output was found. Caught up with the output after every word.
+        let mut at: tokens::Span = tokens::Span { file: importer, ..tokens::Span::default() };  //Where the word being read starts.
+        let mut contents_at: tokens::Span = at;                 //Where the contents or comments being collected started.
+        let mut line_end: tokens::Span = at;                    //Where the last line ended, for the line break pushed at the start of the next.
         let mut line_num: usize = 0;                            //Declare line number counter for debugging output.
         let mut word_num: usize;                            //Declare word number counter for debugging output.
+        let mut error_list: Vec<errors::DiazoError> = Vec::new();   //Errors recorded in recovery mode.
+        let mut line_failed: bool;                              //Whether an error was found on the current line, in which case the rest of it is skipped.
+        let mut fence: Option<(&str, Vec<&str>, tokens::Span, usize)> = None;  //An open fenced code block: the indentation of its opening line, the lines read into it, and where and at which word it was opened.
+
+        //Local macro for reporting an error. Outside of recovery mode this ends the lexer, otherwise the error is recorded and the line abandoned.
+        macro_rules! fail {
+            ($e:expr) => {{
+                let e = $e;
+                if !config.recover {
+                    return Err(e)
+                }
+                error_list.push(e);
+                line_failed = true;
+                break
+            }};
+        }
     
-        //Local function for returning the location of a syntax error.
-        fn error_locator(a: usize, b: usize, c: &str) {
-            eprintln!("Issue found at line {}, word {}, token:{}", a, b, c);
+        //Local macro for noting where the tokens pushed since it was last used were found. Contents and comments start where they were begun, and anything else at the word being read.
+        macro_rules! sync {
+            () => {
+                spans.truncate(output.len());
+                while spans.len() < output.len() {
+                    if let tokens::Tokens::Content(_) | tokens::Tokens::CommentContents(_) = output[spans.len()] {
+                        spans.push(contents_at);
+                        contents_at = at;   //Anything collected after this in the same word starts there too.
+                    } else {
+                        spans.push(at);
+                    }
+                }
+            };
         }
+    
+        //Local function for building a syntax error at the location it was found.
+        let error_locator = |kind: errors::ErrorKind, a: usize, b: usize, c: &str, message: &str| -> errors::DiazoError {
+            errors::DiazoError::Lexer { kind, location: errors::Location { file: config.source.clone(), line: a, column: None, word: Some(b) }, token: c.to_string(), message: message.to_string() }
+        };
         //Local function for checking if a token is in a position in a vector and returning a bool based on that.
-        fn logic_check(v: &Vec<tokens::Tokens>, i: usize, t: tokens::Tokens) -> bool {
+        fn logic_check(v: &[tokens::Tokens], i: usize, t: tokens::Tokens) -> bool {
             if v.len() >= i && (v.len() - i) != 0 {                             //First we check if we can index into the vector.
                 return *v.get(v.len() - 1 - i).unwrap() == t //Then compare what we find with our desired token t.
             }                                                   //Parameter i is the number of places before the end of the vector we are looking at.
             false                                               //If the vector is empty, of course we cannot match t.
         }
-        //Local function for multi-threaded file-handling.
-        fn import_file(filename: &String, output_to_edit: &mut Vec<tokens::Tokens>, typelist_to_edit: &mut Vec<String>) -> Result<(), &'static str> {
-            //Find a file and read it, or else report that an issue has occurred.
-            let s = match fs::read_to_string(&filename) {
-                Ok(s) => s,
-                Err(e) => {
-                    eprintln!("Error occurred while attempting to import a file: {}", e);
-                    return Err("Error, see above. ^^");
-                },
-            };
-            //Lex the file.
-            let (temp1, temp2) = match abridged_lexer(s, &filename) {
-                Ok((v, t)) => (v, t),
-                Err(e) => {
-                    eprintln!("Error occurred while attempting to lex an imported file: {}", e);
-                    return Err("Error, see above. ^^");
-                }
-            };
-            for i in temp1 {
-                output_to_edit.push(i);
+
+        if config.prelude {  //Bring in the standard library before the input, as if it began with *use std.
+            let location = errors::Location { file: config.source.clone(), line: 0, column: None, word: None };
+            for t in import_file(filehandling::STD, importer, config, location, &mut types, graph)? {
+                output.push(t.value);
+                spans.push(t.span);
             }
-            for i in temp2 {
-                typelist_to_edit.push(i);
+            if !output.is_empty() && !logic_check(&output, 0, tokens::Tokens::Linebreak) {
+                output.push(tokens::Tokens::Linebreak);
             }
-            Ok(())
+            sync!();
         }
 
-        for l in input.lines() {                          //Iterate over input linewise.
+        let pragma = pragma(input);
+        let spaces: Option<usize> = match pragma {              //How many spaces make up a level of indentation, if spaces are used.
+            Some((_, Ok(setting))) => setting,
+            _ => config.indent_spaces
+        };
+        let mut next_offset: usize = 0;                         //Where the next line starts in the input.
+        for raw in input.split_inclusive('\n') {               //Iterate over input linewise.
+            let l = raw.strip_suffix('\n').map(|b| b.strip_suffix('\r').unwrap_or(b)).unwrap_or(raw);  //The line without its ending.
+            let line_offset = next_offset;
+            next_offset += raw.len();
+            at = line_end;                                      //Anything pushed before the first word belongs to the end of the last line.
+            if let Some((indent, lines, opened, _)) = &mut fence {  //Lines in a fenced code block are kept as they are, without the indentation of the line that opened it.
+                line_num += 1;
+                line_end = tokens::Span { file: importer, offset: line_offset + l.len(), line: line_num, column: l.chars().count() + 1 };
+                if l.trim() == "```" {
+                    output.push(tokens::Tokens::CodeBlock(lines.join("\n")));
+                    at = *opened;   //The block as a whole starts at its fence.
+                    sync!();
+                    fence = None;
+                } else {
+                    lines.push(l.strip_prefix(*indent).unwrap_or(l));
+                }
+                continue
+            }
             //At the start of each line, reset the head to handle keywords unless a block comment is active.
             mode = if logic_check(&scope_stack, 0, tokens::Tokens::CommentOpen) {
-                if comments_stack.len() != 0 {    //Clear out any comments from the last line.
+                if !comments_stack.is_empty() {    //Clear out any comments from the last line.
                     output.push(tokens::Tokens::CommentContents(comments_stack));
                     if !logic_check(&output, 0, tokens::Tokens::Linebreak) {output.push(tokens::Tokens::Linebreak)};    //Place the linebreak back after our desired content.
                     comments_stack = String::new();
                 }
                 Mode::Comments                
             } else {
-                if line_num > 1 {output.push(tokens::Tokens::Linebreak);}   //Add a linebreak at the start of each line but not the first.
+                if line_num > 0 {output.push(tokens::Tokens::Linebreak);}   //Add a linebreak at the start of each line but not the first.
                 scope_stack.clear();
                 //First check if anything was contained in the contents or comments strings, since switching out of those modes moves to Keys mode.
-                if contents_stack.len() != 0 {    //Handle the text stack first, since contents always come before comments.
+                if !contents_stack.is_empty() {    //Handle the text stack first, since contents always come before comments.
                     let t = logic_check(&output, 0, tokens::Tokens::Linebreak);
                     if t { output.pop(); }               //Remove the linebreak currently at the end of the output vector.
                     output.push(tokens::Tokens::Content(contents_stack));
                     if t { output.push(tokens::Tokens::Linebreak); }  //Place the linebreak back after our desired content.
                     contents_stack = String::new();
                 }
-                if comments_stack.len() != 0 {    //This is how inline comments are handled.
+                if !comments_stack.is_empty() {    //This is how inline comments are handled.
                     let t = logic_check(&output, 0, tokens::Tokens::Linebreak);
                     if t { output.pop(); }                 //Remove the linebreak currently at the end of the output vector.
                     output.push(tokens::Tokens::CommentContents(comments_stack));
@@ -105,21 +418,57 @@ pub mod lexer {
                 Mode::Keys
                 
             };
+            sync!();
             word_num = 0;                                       //Set the word counter to 0.
             let mut line_scope_counter: usize = 0;              //Declare a local scope counter.
             line_num += 1;                                      //Increment the line counter which will be returned in error messages.
+            line_failed = false;
+            let line_start: (usize, usize) = (output.len(), types.len());   //Where this line starts in the output and the type list, so a failed line can be taken back out.
+            let (indent, rest) = l.split_at(l.len() - l.trim_start_matches([' ', '\t']).len());
+            let levels = indent_levels(indent, spaces);
+            let mut starts: Vec<(usize, usize)> = levels.iter().map(|&o| (o, o + 1)).collect();     //The indentation is all single byte characters.
+            starts.extend(word_starts(rest).into_iter().map(|(o, c)| (o + indent.len(), c + indent.len())));
 
-            'words: for w in l.replace("\t", " *tab! ").split_whitespace() {     //Replace hard tab characters with a keyword, iterate over separated whitespaces.
+            //Replace each level of indentation and any other hard tab characters with a keyword, then iterate over separated whitespaces.
+            let words = "*tab! ".repeat(levels.len()) + &rest.replace("\t", " *tab! ");
+            let word_count = words.split_whitespace().count();
+            'words: for w in words.split_whitespace() {
+                let escaped = escape(w);
+                let w = escaped.as_str();    //Escaped symbols are hidden from everything below.
                 word_num += 1;  //Increment the word counter which will be returned in error messages.
+                sync!();        //Everything pushed so far was found at the last word.
+                if let Some((offset, column)) = starts.get(word_num - 1) {
+                    at = tokens::Span { file: importer, offset: line_offset + offset, line: line_num, column: *column };
+                }
+                if word_num == 1 && matches!(mode, Mode::Keys) && !rest.is_empty() {   //Indentation only matters on lines with something in them, and not inside block comments.
+                    if let Some((kind, message)) = indent_error(indent, spaces) {
+                        fail!(error_locator(kind, line_num, word_num, &indent.escape_debug().to_string(), &message));
+                    }
+                }
+                if contents_stack.is_empty() && comments_stack.is_empty() {
+                    contents_at = at;
+                }
+                //A word starting with ``` at the end of a line opens a fenced code block, unless it's in a comment, a declaration or an inline block. What follows the backticks is the language.
+                if word_num == word_count && w.starts_with("```") && !matches!(mode, Mode::Comments) && scope_stack.is_empty()
+                    && !matches!(output.last(), Some(tokens::Tokens::CodeBlockOpen | tokens::Tokens::MathBlockOpen)) {
+                    if !contents_stack.is_empty() {
+                        output.push(tokens::Tokens::Content(std::mem::take(&mut contents_stack)));
+                    }
+                    output.push(tokens::Tokens::CodeFence(unescape(&w[3..])));
+                    fence = Some((indent, Vec::new(), at, word_num));
+                    continue
+                }
                 match mode {
                     Mode::Keys => {
                         //Once that's done, get to work matching different tokens.
                         match w {
                             "*tab!" => {                            //Handle tabs, which are used to determine scope.
-                                if word_num == 1 || output.get(output.len() - 1) == Some(&tokens::Tokens::Tab) {
-                                    line_scope_counter += 1;        //Increment scope counter only if tabs are at the start of the line.
+                                //Only the run of tabs at the start of a line is indentation. Any later tab is whitespace between words, and gets no token,
+                                //so that e.g. *type<tab>name is read the same as *type name rather than as a tab inside the declaration.
+                                if word_num == 1 || output.last() == Some(&tokens::Tokens::Tab) {
+                                    line_scope_counter += 1;
+                                    output.push(tokens::Tokens::Tab);
                                 }
-                                output.push(tokens::Tokens::Tab);   //Add to output vector.
                             },
                             "//" => {                                       //Line Comments
                                 mode = Mode::Comments;                      //Switch head mode to comments.
@@ -130,26 +479,31 @@ pub mod lexer {
                                 scope_stack.push(tokens::Tokens::CommentOpen);    //Push the comment block symbol to the scope stack. 
                                 output.push(tokens::Tokens::CommentOpen);         // ^ Its presence stops the head from switching back to Keys mode after each line.
                             },
+                            "*indent" => {  //Pragma setting how the file is indented. It was read before the lexer started, so all that's left is to check where it is.
+                                match pragma {
+                                    Some((line, Err(message))) if line == line_num => fail!(error_locator(errors::ErrorKind::IndentPragma, line_num, word_num, w, message)),
+                                    Some((line, Ok(_))) if line == line_num => break 'words,    //Nothing is output for the pragma.
+                                    _ => fail!(error_locator(errors::ErrorKind::IndentPragma, line_num, word_num, w, "Invalid syntax: *indent can only be used at the top of a file, before anything but blank lines and comments."))
+                                }
+                            },
                             "*type" => {    //Keyword for declaring new types
                                 //Check if type has appeared in a scope somewhere, which it really shouldn't.
                                 if line_scope_counter != 0 {
-                                    error_locator(line_num, word_num, w);
-                                    return Err("Invalid syntax: *type has been placed in a scope somewhere, it should not have been. There is no place for a type declaration in a scope. (Remove any tabs before this *type)");
+                                    fail!(error_locator(errors::ErrorKind::TypeInScope, line_num, word_num, w, "Invalid syntax: *type has been placed in a scope somewhere, it should not have been. There is no place for a type declaration in a scope. (Remove any tabs before this *type)"));
                                 }
                                 scope_stack.push(tokens::Tokens::TypeKeyword);  //Push the keyword to the scope stack. 
                                 output.push(tokens::Tokens::TypeKeyword);       // ^ Its presence allows for handling of new names for types in the "other" arm.
                             },
                             "*use" => { //Keyword for noting that another file's declarations will be used.
                                 //Check for a variety of errors that can occur with the keyword's use.
-                                if logic_check(&scope_stack, 0, tokens::Tokens::UseKeyword) {
-                                    error_locator(line_num, word_num, w);
-                                    return Err("Invalid syntax: *use has already been called, you cannot have multiple on the same line.");
+                                if !config.allow_imports {
+                                    fail!(error_locator(errors::ErrorKind::ImportNotAllowed, line_num, word_num, w, "Invalid syntax: *use has been called, but imports have been turned off here."));
+                                } else if logic_check(&scope_stack, 0, tokens::Tokens::UseKeyword) {
+                                    fail!(error_locator(errors::ErrorKind::RepeatedUse, line_num, word_num, w, "Invalid syntax: *use has already been called, you cannot have multiple on the same line."));
                                 } else if line_scope_counter != 0 {
-                                    error_locator(line_num, word_num, w);
-                                    return Err("Invalid syntax: *use has been placed in a scope somewhere, it should not have been. There is no place for an import in a scope. (Remove any tabs before this *use)");
+                                    fail!(error_locator(errors::ErrorKind::UseInScope, line_num, word_num, w, "Invalid syntax: *use has been placed in a scope somewhere, it should not have been. There is no place for an import in a scope. (Remove any tabs before this *use)"));
                                 } else if logic_check(&scope_stack, 0, tokens::Tokens::Assignment) || logic_check(&scope_stack, 0, tokens::Tokens::TypeKeyword) {
-                                    error_locator(line_num, word_num, w);
-                                    return Err("Invalid syntax: *use has been called in a type declaration. You can't do this.");
+                                    fail!(error_locator(errors::ErrorKind::UseInDeclaration, line_num, word_num, w, "Invalid syntax: *use has been called in a type declaration. You can't do this."));
                                 }
                                 scope_stack.push(tokens::Tokens::UseKeyword);   //Place the *use into the scope stack. The next token must be a filename becuase of this.
                                 output.push(tokens::Tokens::UseKeyword);
@@ -159,11 +513,9 @@ pub mod lexer {
                                     It might be used without an accompanying type keyword,
                                     or multiple words might have been put in place of the type's name being assigned */
                                 if !logic_check(&scope_stack, 0, tokens::Tokens::TypeKeyword) {
-                                    error_locator(line_num, word_num, w);
-                                    return Err("Invalid syntax: Assignment operator used somewhere other than a type assignment.");
+                                    fail!(error_locator(errors::ErrorKind::MisplacedAssignment, line_num, word_num, w, "Invalid syntax: Assignment operator used somewhere other than a type assignment."));
                                 } else if logic_check(&scope_stack, 0, tokens::Tokens::TypeKeyword) && !logic_check(&output, 1, tokens::Tokens::TypeKeyword) {
-                                    error_locator(line_num, word_num - 2, w);
-                                    return Err("Invalid syntax: Type names should only be one word. Try using underscores, or check the declaration.");
+                                    fail!(error_locator(errors::ErrorKind::MultiWordTypeName, line_num, word_num - 2, w, "Invalid syntax: Type names should only be one word. Try using underscores, or check the declaration."));
                                 }
                                 scope_stack.push(tokens::Tokens::Assignment);
                                 output.push(tokens::Tokens::Assignment);
@@ -174,22 +526,27 @@ pub mod lexer {
                                     scope_stack.pop();  //Remove the *use keyword from the scope_stack
                                     output.push(tokens::Tokens::Filename(other.to_string()));
                                     output.push(tokens::Tokens::Linebreak);
-                                    match import_file(&other.to_string(), &mut output, &mut types) {
-                                        Ok(_) => (),
-                                        Err(e) => {
-                                            eprintln!("Error occurred while attempting to lex an imported file: {}", e);
-                                            return Err("Error, see above. ^^");
-                                        }
-                                    };
+                                    let location = errors::Location { file: config.source.clone(), line: line_num, column: None, word: Some(word_num) };
+                                    sync!();    //The imported tokens come with their own spans.
+                                    match import_file(&unescape(other), importer, config, location, &mut types, graph) {
+                                        Ok(imported) => for t in imported {
+                                            output.push(t.value);
+                                            spans.push(t.span);
+                                        },
+                                        Err(e) => fail!(e)
+                                    }
                                     continue 'words
                                 }
                                 //Check if we are immediately after a type keyword but before the arrow.
                                 if logic_check(&output, 0, tokens::Tokens::TypeKeyword) && logic_check(&scope_stack, 0, tokens::Tokens::TypeKeyword) {
-                                    if types.contains(&other.clone().to_string()) {             //Prevent type declarations to the same name.
-                                        error_locator(line_num, word_num, w);
-                                        return Err("Invalid syntax: It appears this type has been declared before, the namespace is already occupied!");
+                                    if types.contains(&other.to_string()) {             //Prevent type declarations to the same name.
+                                        let message = match graph.declared_in(other) {  //Say where the first declaration is if it was imported.
+                                            Some(file) => format!("Invalid syntax: This type has already been declared in {}, the namespace is already occupied!", file),
+                                            None => "Invalid syntax: It appears this type has been declared before, the namespace is already occupied!".to_string()
+                                        };
+                                        fail!(error_locator(errors::ErrorKind::DuplicateType, line_num, word_num, w, &message));
                                     }
-                                    types.push(other.clone().to_string());                      //Add the new type as a valid option for use in future code.
+                                    types.push(other.to_string());                      //Add the new type as a valid option for use in future code.
                                     output.push(tokens::Tokens::TypeName(other.to_string()));   //Add the token onto the output vector too.
                                     continue
                                 }
@@ -201,37 +558,38 @@ pub mod lexer {
                                         output.push(tokens::Tokens::Any);
                                         continue
                                     }
-                                    if types.contains(&String::from(other.clone())) {
+                                    if types.contains(&String::from(other)) {
                                         output.push(tokens::Tokens::TypeAsDeclarationParameter(String::from(other)));
                                         continue
                                     }
-                                    match other.clone().chars().nth(0).unwrap() {
+                                    if let Some(a) = other.strip_prefix('@').filter(|a| !a.is_empty()) {  //Annotations, which mark the type for tools like exporters.
+                                        output.push(tokens::Tokens::Annotation(a.to_string()));
+                                        continue
+                                    }
+                                    match other.chars().next().unwrap() {
                                         'e' => {
-                                            let a = other.clone();  //Declare clone of other for checking around with logic.
+                                            let a = other;  //Declare copy of other for checking around with logic.
                                             //Make sure that there is a .. in between the e and whatever follows. Sadly we cannot check if there is an n or number yet.
-                                            if a.len() > 1 && (a.chars().nth(1).unwrap(), a.chars().nth(2).unwrap()) != ('.', '.') {
-                                                error_locator(line_num, word_num, w);
-                                                return Err("Invalid syntax: Something besides \"..\" is separating the e from the number/letter here.
-                                                            Alternatively there's something else entirely following the e");
+                                            if a.len() > 1 && a.get(1..3) != Some("..") {
+                                                fail!(error_locator(errors::ErrorKind::InvalidElementArgument, line_num, word_num, w, "Invalid syntax: Something besides \"..\" is separating the e from the number/letter here.
+                                                            Alternatively there's something else entirely following the e"));
                                             }
                                             output.push(tokens::Tokens::E(other.to_string()));
                                             continue
                                         },
                                         'c' => {
-                                            let a = other.clone();  //Declare clone of other for checking around with logic.
+                                            let a = other;  //Declare copy of other for checking around with logic.
                                             //Make sure that there is a .. in between the c and whatever follows. Sadly we cannot check if there is an n or number yet.
-                                            if a.len() > 1 && (a.chars().nth(1).unwrap(), a.chars().nth(2).unwrap()) != ('.', '.') {
-                                                error_locator(line_num, word_num, w);
-                                                return Err("Invalid syntax: Something besides \"..\" is separating the c from the number/letter here.
-                                                            Alternatively there's something else entirely following the c");
+                                            if a.len() > 1 && a.get(1..3) != Some("..") {
+                                                fail!(error_locator(errors::ErrorKind::InvalidContentArgument, line_num, word_num, w, "Invalid syntax: Something besides \"..\" is separating the c from the number/letter here.
+                                                            Alternatively there's something else entirely following the c"));
                                             }
                                             output.push(tokens::Tokens::C(other.to_string()));
                                             continue
                                         },
                                         _other => {  //The only things that can be in the arguments section are c, e, any, or a type that's been declared.
-                                        error_locator(line_num, word_num, w);
-                                            return Err("Invalid syntax: Something other than a type name, \"e\", \"c\", or \"any\".
-                                                        If it is a type name, it hasn't been declared yet and so cannot be recognized.");
+                                        fail!(error_locator(errors::ErrorKind::UnknownDeclarationArgument, line_num, word_num, w, "Invalid syntax: Something other than a type name, \"e\", \"c\", or \"any\".
+                                                        If it is a type name, it hasn't been declared yet and so cannot be recognized."));
                                         }
                                     }
                                 }
@@ -249,7 +607,7 @@ pub mod lexer {
                                     continue
                                 }
                                 //Otherwise, we are probably reading for types to be instantiated. Check if we're reading a type that's been declared.
-                                if types.contains(&other.clone().to_string()) {
+                                if types.contains(&other.to_string()) {
                                     output.push(tokens::Tokens::TypeInstance(other.to_string()));
                                     mode = Mode::Contents;  //We've just identified that a type has been instantiated. This transitions the head to read for contents instead.
                                 } else {
@@ -284,7 +642,11 @@ pub mod lexer {
                     },
                     Mode::Contents => {
                         //There are a few things we can encounter once we have begun reading in contents mode.
-                        //First, symbols for controlling formatting blocks.
+                        //Tabs in the middle of a line have no function, they are just whitespace here.
+                        if w == "*tab!" {
+                            continue 'words
+                        }
+                        //Next, symbols for controlling formatting blocks.
                         if w == "[[" {
                             output.push(tokens::Tokens::Content(contents_stack.clone()));   //Interrupt the contents stack and send it to the output stack.
                             contents_stack.clear();                                         //Clear contents stack.
@@ -313,6 +675,7 @@ pub mod lexer {
                             contents_stack.clear();                                                 //Clear the contents stack.
                             output.push(tokens::Tokens::CodeBlockOpen);                             //Send the symbol to the output stack.
                             contents_stack.push_str(w.replace("[[", "").as_str()); //Add whatever follows the symbol to the contents stack.
+                            contents_stack.push(' ');
                             continue 'words
                         } else if w.contains("]]") {                  //The procedure is slightly different for the end symbols. Not only is the order
                             for i in tokens::separator_list() { //in which the default procedure is executed different, but we must also consider the possibility of both content and a separator being attached to the symbol.
@@ -335,6 +698,7 @@ pub mod lexer {
                             contents_stack.clear();
                             output.push(tokens::Tokens::MathBlockOpen);
                             contents_stack.push_str(w.replace("{{", "").as_str());
+                            contents_stack.push(' ');
                             continue 'words
                         } else if w.contains("}}") {    //Similar procedure to the end code block symbol.
                             for i in tokens::separator_list() {
@@ -347,7 +711,7 @@ pub mod lexer {
                                     continue 'words
                                 }
                             }
-                            contents_stack.push_str(w.replace("]]", " ").as_str());
+                            contents_stack.push_str(w.replace("}}", " ").as_str());
                             output.push(tokens::Tokens::Content(contents_stack.clone()));
                             contents_stack.clear();
                             output.push(tokens::Tokens::MathBlockClose);
@@ -355,10 +719,13 @@ pub mod lexer {
                         }
 
                         //Alternatively, a separator, of which there are some varieties but all have the same functionality. They just look different.
-                        for i in tokens::separator_list() {
-                            if i == w {
-                                output.push(tokens::Tokens::Separator(w.to_string()));
+                        if tokens::separator_list().contains(&w) {
+                            if !contents_stack.is_empty() {                                 //Terminate the current contents stack if anything is in it.
+                                output.push(tokens::Tokens::Content(contents_stack.clone()));
+                                contents_stack.clear();
                             }
+                            output.push(tokens::Tokens::Separator(w.to_string()));          //Send the separator to the output stack.
+                            continue 'words
                         }
                         //If we aren't dealing with a separator, we could be loading things into the contents String. But it might have a separator attached so let's clear that up too.
                         for i in tokens::separator_list() {     //Compare it to the list of separators... only works on 2 character-long separators.
@@ -382,8 +749,7 @@ pub mod lexer {
                                 mode = Mode::Keys;
                                 continue
                             } else {
-                                error_locator(line_num, word_num, w);
-                                return Err("Invalid syntax: You've tried to close a block comment but this isn't a block comment!");
+                                fail!(error_locator(errors::ErrorKind::UnmatchedCommentClose, line_num, word_num, w, "Invalid syntax: You've tried to close a block comment but this isn't a block comment!"));
                             }
                         }
                         //Check that the word doesn't end in the end comment block symbol, which it could if no space has been placed there.
@@ -396,8 +762,7 @@ pub mod lexer {
                                 mode = Mode::Keys;
                                 continue
                             } else {
-                                error_locator(line_num, word_num, w);
-                                return Err("Invalid syntax: You've tried to close a block comment but this isn't a block comment!");
+                                fail!(error_locator(errors::ErrorKind::UnmatchedCommentClose, line_num, word_num, w, "Invalid syntax: You've tried to close a block comment but this isn't a block comment!"));
                             }
                         }
                         //Alternatively, comments, which are loaded into a String.
@@ -406,651 +771,691 @@ pub mod lexer {
                     },
                 }
             }
+            if line_failed {    //Take everything from the failed line back out, as if it had been left blank.
+                output.truncate(line_start.0);
+                types.truncate(line_start.1);
+                contents_stack.clear();
+                comments_stack.clear();
+                scope_stack.clear();
+            }
+            sync!();
+            line_end = tokens::Span { file: importer, offset: line_offset + l.len(), line: line_num, column: l.chars().count() + 1 };
+        }
+        at = line_end;
+        if let Some((_, lines, opened, word)) = fence { //The input ended without closing a fenced code block. Everything after the fence is still kept in it.
+            output.push(tokens::Tokens::CodeBlock(lines.join("\n")));
+            at = opened;
+            sync!();
+            let e = error_locator(errors::ErrorKind::UnclosedCodeFence, opened.line, word, "```", "Invalid syntax: This fenced code block is never closed. End it with a line that only has ``` on it.");
+            if !config.recover {
+                return Err(e)
+            }
+            error_list.push(e);
+            at = line_end;
         }
         //Check if the comments or contents stacks are empty or not. It there is something there, empty it out.
-        if contents_stack.len() != 0 {    //Technically these two situations should be mutually exclusive.
+        if !contents_stack.is_empty() {    //Technically these two situations should be mutually exclusive.
             output.push(tokens::Tokens::Content(contents_stack));
         }
-        if comments_stack.len() != 0 {
+        if !comments_stack.is_empty() {
             output.push(tokens::Tokens::CommentContents(comments_stack));
         }
-        Ok(output)  //Since everything has been okay so far, return the output vector wrapped in Ok!
+        sync!();
+        let mut output: Vec<tokens::Token> = output.into_iter().zip(spans).map(|(value, span)| {
+            let value = match value {   //Escaped symbols only become themselves again once the lexer is done looking for them.
+                other if span.file != importer => other,    //Imported tokens were put back by the lexer that read them.
+                tokens::Tokens::Filename(s) => tokens::Tokens::Filename(unescape(&s)),
+                tokens::Tokens::CommentContents(s) => tokens::Tokens::CommentContents(unescape(&s)),
+                tokens::Tokens::TypeName(s) => tokens::Tokens::TypeName(unescape(&s)),
+                tokens::Tokens::E(s) => tokens::Tokens::E(unescape(&s)),
+                tokens::Tokens::C(s) => tokens::Tokens::C(unescape(&s)),
+                tokens::Tokens::TypeAsDeclarationParameter(s) => tokens::Tokens::TypeAsDeclarationParameter(unescape(&s)),
+                tokens::Tokens::Annotation(s) => tokens::Tokens::Annotation(unescape(&s)),
+                tokens::Tokens::TypeInstance(s) => tokens::Tokens::TypeInstance(unescape(&s)),
+                tokens::Tokens::Content(s) => tokens::Tokens::Content(unescape(&s)),
+                other => other  //Separators and languages of fenced code blocks are already themselves, and fenced code blocks are kept exactly as written.
+            };
+            tokens::Token { value, span }
+        }).collect();
+        if config.declarations_only {
+            output = declarations(output);
+        }
+        Ok((output, types, error_list))  //Since everything has been okay so far (or been recovered from), return the output vector wrapped in Ok!
     }
+}
 
-    //Abridged lexer, which cannot perform filehandling (no imports). Prevents recursive behavior (a file imports another file which imports another file).
-    //This version of the lexer is called in the new threads when importing other files.
-    //It also only places type declarations into the output.
-    fn abridged_lexer(input: String, filename: &String) -> Result<(Vec<tokens::Tokens>, Vec<String>), &'static str> {
+//Module containing the concrete syntax tree builder, a second reading of the source that keeps every character so that tools can write it back out.
+//It follows the lexer's rules for where comments, separators and formatting blocks are found, but never fails and never looks at imports.
+pub mod cst {
+    use crate::core::{tokens, syntax::{SyntaxKind, SyntaxNode}};
 
-        //Declarations for the lexer's operation.
-        let mut scope_stack: Vec<tokens::Tokens> = Vec::new();  //Declare a vector functioning as a stack for handling scope. The type of token in the stack determines the head's reading mode.
-        let mut contents_stack: String = String::new();         //Declare a vector to hold contents that have been collected.
-        let mut comments_stack: String = String::new();         //Declare a vector to hold comments that have been collected. These have to be separate since the tokens are different.
-        #[derive(Debug)]
-        enum Mode {                                             //Define an enum for the head's operating mode.
-            Keys,     // The head is reading for diazo keywords
-            Contents, // The head is reading for contents which don't have functions in the language
-            Comments, // The head is reading comments and ignoring them
+    pub fn cst(input: &str) -> SyntaxNode {
+        let mut in_comment: bool = false;   //Whether a block comment is still open from an earlier line.
+        let mut in_fence: bool = false;     //Whether a fenced code block is still open from an earlier line.
+        let lines: Vec<SyntaxNode> = input.split_inclusive('\n').map(|l| {
+            let body = l.strip_suffix('\n').map(|b| b.strip_suffix('\r').unwrap_or(b)).unwrap_or(l);
+            if in_fence {
+                in_fence = body.trim() != "```";
+                return code_line(body, &l[body.len()..], in_fence)
+            }
+            let mut node = line(body, &l[body.len()..], &mut in_comment);
+            in_fence = open_fence(&mut node);
+            node
+        }).collect();
+        SyntaxNode::node(SyntaxKind::Document, lines)
+    }
+
+    //Check whether a line opens a fenced code block, marking the fence if it does. Like the lexer, the fence has to be the last word of a line of markup.
+    fn open_fence(node: &mut SyntaxNode) -> bool {
+        if node.kind != SyntaxKind::ExpressionLine {
+            return false
         }
-        let mut mode: Mode;                                     //Declare a variable to store the current mode.
-        let mut types: Vec<String> = Vec::new();                //Initialize a vector to store the types that the lexer can recognize.
-        let mut syntax_check_output:Vec<tokens::Tokens> = Vec::new();       //Initialize an output vector which is used for ensuring that the imported file has enough good syntax to be trustworthy.
-        let mut final_output: Vec<tokens::Tokens> = Vec::new();             //Initialize output that will actually be used.
-        let mut line_num: usize = 0;                            //Declare line number counter for debugging output.
-        let mut word_num: usize;                                //Declare word number counter for debugging output.
-    
-        //Local function for returning the location of a syntax error.
-        fn error_locator(a: &String, b: usize, c: usize, d: &str) {
-            eprintln!("Issue in imported file: {}.\nIssue found at line {}, word {}, token:{}", a, b, c, d);
+        let mut words = node.children.iter_mut().rev().filter(|l| !matches!(l.kind, SyntaxKind::Whitespace | SyntaxKind::Newline));
+        match (words.next(), words.next()) {
+            (Some(last), before) if last.kind == SyntaxKind::Word && last.text.starts_with("```")
+                && before.as_ref().is_none_or(|b| !matches!(b.kind, SyntaxKind::CodeOpen | SyntaxKind::MathOpen)) => {
+                last.kind = SyntaxKind::Fence;
+                true
+            },
+            _ => false
         }
-        //Local function for checking if a token is in a position in a vector and returning a bool based on that.
-        fn logic_check(v: &Vec<tokens::Tokens>, i: usize, t: tokens::Tokens) -> bool {
-            if v.len() >= i && (v.len() - i) != 0 {                             //First we check if we can index into the vector.
-                return *v.get(v.len() - 1 - i).unwrap() == t //Then compare what we find with our desired token t.
-            }                                                   //Parameter i is the number of places before the end of the vector we are looking at.
-            false                                               //If the vector is empty, of course we cannot match t.
+    }
+
+    //Read a line inside a fenced code block, which is kept whole, or the fence closing it.
+    fn code_line(body: &str, ending: &str, open: bool) -> SyntaxNode {
+        let mut leaves: Vec<SyntaxNode> = Vec::new();
+        if open {
+            if !body.is_empty() {
+                leaves.push(SyntaxNode::leaf(SyntaxKind::Code, body));
+            }
+        } else {
+            let start = body.len() - body.trim_start().len();
+            let end = body.trim_end().len();
+            if start > 0 {
+                leaves.push(SyntaxNode::leaf(SyntaxKind::Indent, &body[..start]));
+            }
+            leaves.push(SyntaxNode::leaf(SyntaxKind::Fence, &body[start..end]));
+            if end < body.len() {
+                leaves.push(SyntaxNode::leaf(SyntaxKind::Whitespace, &body[end..]));
+            }
         }
+        if !ending.is_empty() {
+            leaves.push(SyntaxNode::leaf(SyntaxKind::Newline, ending));
+        }
+        SyntaxNode::node(SyntaxKind::CodeLine, leaves)
+    }
 
-        for l in input.lines() {                          //Iterate over input linewise.
-            //At the start of each line, reset the head to handle keywords unless a block comment is active.
-            mode = if logic_check(&scope_stack, 0, tokens::Tokens::CommentOpen) {
-                if comments_stack.len() != 0 {    //Clear out any comments from the last line.
-                    syntax_check_output.push(tokens::Tokens::CommentContents(comments_stack));
-                    if !logic_check(&syntax_check_output, 0, tokens::Tokens::Linebreak) {syntax_check_output.push(tokens::Tokens::Linebreak)};    //Place the linebreak back after our desired content.
-                    comments_stack = String::new();
+    //Read a single line, given without its line ending.
+    fn line(body: &str, ending: &str, in_comment: &mut bool) -> SyntaxNode {
+        let mut leaves: Vec<SyntaxNode> = Vec::new();
+        let mut kind: Option<SyntaxKind> = None;                                //Decided by the first word that isn't in a comment.
+        let mut comment_start: Option<usize> = if *in_comment { Some(0) } else { None };  //Where the open block comment starts on this line.
+        let mut search_from: usize = 0;                                         //Where to start looking for the */ closing it.
+        let end: usize = body.trim_end().len();                                 //Trailing whitespace is kept apart from what comes before it.
+
+        for (start, run) in runs(&body[..end]) {
+            let space = run.starts_with(char::is_whitespace);
+            if comment_start.is_none() {
+                if space {
+                    leaves.push(SyntaxNode::leaf(if start == 0 { SyntaxKind::Indent } else { SyntaxKind::Whitespace }, run));
+                    continue
                 }
-                Mode::Comments                
-            } else {
-                if line_num > 1 {syntax_check_output.push(tokens::Tokens::Linebreak);}   //Add a linebreak at the start of each line but not the first.
-                if scope_stack.contains(&tokens::Tokens::TypeKeyword) { //Check if we just came from a type declaration.
-                    final_output.push(tokens::Tokens::Linebreak);       //In which case, insert a line break.
+                if kind != Some(SyntaxKind::ExpressionLine) {   //Like the lexer, comments are only found before the contents of a line begin.
+                    match (tokens::find_unescaped(run, "//"), tokens::find_unescaped(run, "/*")) {
+                        (Some(a), b) if b.is_none_or(|b| a < b) => {  //A line comment takes the rest of the line.
+                            leaves.push(SyntaxNode::leaf(SyntaxKind::Comment, &body[start..end]));
+                            break
+                        },
+                        (_, Some(b)) => {
+                            comment_start = Some(start);
+                            search_from = start + b + 2;
+                            *in_comment = true;
+                        },
+                        _ => ()
+                    }
                 }
-                scope_stack.clear();
-                //First check if anything was contained in the contents or comments strings, since switching out of those modes moves to Keys mode.
-                if contents_stack.len() != 0 {    //Handle the text stack first, since contents always come before comments.
-                    let t = logic_check(&syntax_check_output, 0, tokens::Tokens::Linebreak);
-                    if t { syntax_check_output.pop(); }               //Remove the linebreak currently at the end of the output vector.
-                    syntax_check_output.push(tokens::Tokens::Content(contents_stack));
-                    if t { syntax_check_output.push(tokens::Tokens::Linebreak); }  //Place the linebreak back after our desired content.
-                    contents_stack = String::new();
+                if comment_start.is_none() {
+                    word(run, &mut kind, &mut leaves);
+                    continue
                 }
-                if comments_stack.len() != 0 {    //This is how inline comments are handled.
-                    let t = logic_check(&syntax_check_output, 0, tokens::Tokens::Linebreak);
-                    if t { syntax_check_output.pop(); }                 //Remove the linebreak currently at the end of the output vector.
-                    syntax_check_output.push(tokens::Tokens::CommentContents(comments_stack));
-                    if t { syntax_check_output.push(tokens::Tokens::Linebreak); }             //Place the linebreak back after our desired content.
-                    comments_stack = String::new();
+            }
+            //Inside a block comment, everything up to the end of the word holding the */ belongs to the comment.
+            let run_end = start + run.len();
+            if !space && tokens::find_unescaped(&body[search_from.max(start)..run_end], "*/").is_some() {
+                leaves.push(SyntaxNode::leaf(SyntaxKind::Comment, &body[comment_start.unwrap()..run_end]));
+                comment_start = None;
+                *in_comment = false;
+            }
+        }
+        if let Some(c) = comment_start.filter(|c| *c < end) {   //The comment carries on past this line.
+            leaves.push(SyntaxNode::leaf(SyntaxKind::Comment, &body[c..end]));
+        }
+        if end < body.len() {
+            leaves.push(SyntaxNode::leaf(if end == 0 { SyntaxKind::Indent } else { SyntaxKind::Whitespace }, &body[end..]));
+        }
+        if !ending.is_empty() {
+            leaves.push(SyntaxNode::leaf(SyntaxKind::Newline, ending));
+        }
+        let kind = kind.unwrap_or(if leaves.iter().any(|l| l.kind == SyntaxKind::Comment) { SyntaxKind::CommentLine } else { SyntaxKind::BlankLine });
+        SyntaxNode::node(kind, leaves)
+    }
+
+    //Read a word outside of comments, deciding the kind of the line if it is the first.
+    fn word(text: &str, kind: &mut Option<SyntaxKind>, leaves: &mut Vec<SyntaxNode>) {
+        let seen = |k: SyntaxKind| leaves.iter().any(|l| l.kind == k);
+        let leaf_kind = match kind {
+            None => match text {
+                "*type" => { *kind = Some(SyntaxKind::DeclarationLine); SyntaxKind::TypeKeyword },
+                "*use" => { *kind = Some(SyntaxKind::UseLine); SyntaxKind::UseKeyword },
+                _ => {
+                    *kind = Some(SyntaxKind::ExpressionLine);
+                    leaves.extend(split(text, true));
+                    return
                 }
-                Mode::Keys
+            },
+            Some(SyntaxKind::DeclarationLine) => match (seen(SyntaxKind::TypeName), seen(SyntaxKind::Assignment)) {
+                (_, false) if text == "=>" => SyntaxKind::Assignment,
+                (false, false) => SyntaxKind::TypeName,
+                (_, true) if text.len() > 1 && text.starts_with('@') => SyntaxKind::Annotation,
+                (_, true) => SyntaxKind::Parameter,
+                _ => SyntaxKind::Word   //Any more words before the =>, which the lexer reports as a multi-word type name.
+            },
+            Some(SyntaxKind::UseLine) if !seen(SyntaxKind::Filename) => SyntaxKind::Filename,
+            Some(SyntaxKind::ExpressionLine) => {
+                leaves.extend(split(text, false));
+                return
+            },
+            _ => SyntaxKind::Word
+        };
+        leaves.push(SyntaxNode::leaf(leaf_kind, text));
+    }
+
+    //Split a word of contents around any formatting block symbols attached to it, and a separator that the lexer reads as ending the field right there.
+    //Escaped symbols stay in the word.
+    fn split(text: &str, first: bool) -> Vec<SyntaxNode> {
+        let symbols = [("[[", SyntaxKind::CodeOpen), ("]]", SyntaxKind::CodeClose), ("{{", SyntaxKind::MathOpen), ("}}", SyntaxKind::MathClose)];
+        let separator = tokens::separator_list().into_iter().find(|s| separator_ends(text, s, first));
+        let mut output: Vec<SyntaxNode> = Vec::new();
+        let mut rest = &text[..text.len() - separator.map_or(0, str::len)];
+        //Take off the earliest symbol each time round, along with the word before it.
+        while let Some((at, symbol, kind)) = symbols.iter().filter_map(|(s, k)| tokens::find_unescaped(rest, s).map(|at| (at, *s, *k))).min_by_key(|(at, _, _)| *at) {
+            if at > 0 {
+                output.push(SyntaxNode::leaf(SyntaxKind::Word, &rest[..at]));
+            }
+            output.push(SyntaxNode::leaf(kind, symbol));
+            rest = &rest[at + symbol.len()..];
+        }
+        if !rest.is_empty() {
+            output.push(SyntaxNode::leaf(SyntaxKind::Word, rest));
+        }
+        if let Some(s) = separator {
+            output.push(SyntaxNode::leaf(SyntaxKind::Separator, s));
+        }
+        output
+    }
+
+    //Whether a word ends in a separator that would mean the same written apart from it. The lexer ends the field after any word holding a separator,
+    //wherever the separator is, and turns a word holding [[ or {{ into code or math whole. Apart from a separator on its own, the first word of a line
+    //keeps its separator too, since without it the word could become a type.
+    fn separator_ends(text: &str, separator: &str, first: bool) -> bool {
+        let Some(before) = text.strip_suffix(separator) else { return false };
+        if tokens::find_unescaped(text, separator) != Some(before.len()) || (first && !before.is_empty()) {
+            return false
+        }
+        let before = before.strip_suffix("]]").or_else(|| before.strip_suffix("}}")).unwrap_or(before);     //A block closed right before the separator.
+        tokens::separator_list().into_iter().chain(["[[", "]]", "{{", "}}"]).all(|s| tokens::find_unescaped(before, s).is_none())
+    }
+
+    //Split a line into runs of whitespace and runs of everything else, along with where each starts.
+    fn runs(text: &str) -> Vec<(usize, &str)> {
+        let mut output: Vec<(usize, &str)> = Vec::new();
+        let mut start: usize = 0;
+        let mut space: Option<bool> = None;
+        for (i, c) in text.char_indices() {
+            if space != Some(c.is_whitespace()) {
+                if i > start {
+                    output.push((start, &text[start..i]));
+                }
+                start = i;
+                space = Some(c.is_whitespace());
+            }
+        }
+        if start < text.len() {
+            output.push((start, &text[start..]));
+        }
+        output
+    }
+}
+
+//Module containing the formatter, which rewrites a file in the canonical style by working over its concrete syntax tree.
+pub mod formatter {
+    use crate::{cst, lexer};
+    use crate::core::syntax::{SyntaxKind, SyntaxNode};
+
+    pub const TAB_WIDTH: usize = 4;     //The width of a tab when lining up comments.
+
+    //A line after formatting. Declarations are kept in pieces until the lines around them are known, so they can be lined up.
+    enum Formatted {
+        Declaration { head: String, tail: String, comment: Option<String>, ending: String },   //"*type name", the rest of the declaration and its trailing comment.
+        Other(String, String)   //The line and its ending.
+    }
+
+    pub fn format(input: &str) -> String {
+        format_with(input, None)
+    }
+
+    //Format a file read with space indentation of the given width, as with the lexer's indent_spaces. An *indent pragma in the file takes precedence.
+    pub fn format_with(input: &str, indent_spaces: Option<usize>) -> String {
+        //Files indented with spaces keep those spaces, and everything else is indented with tabs.
+        let spaces = match lexer::pragma(input) {
+            Some((_, Ok(setting))) => setting,
+            _ => indent_spaces
+        };
+        let unit = spaces.map_or("\t".to_string(), |n| " ".repeat(n));
+        let mut lines: Vec<Formatted> = cst::cst(input).children.iter().map(|l| line(l, &unit, spaces)).collect();
+        //Files end in a single line ending, with no blank lines after the last one.
+        while matches!(lines.last(), Some(Formatted::Other(l, _)) if l.is_empty()) {
+            lines.pop();
+        }
+        match lines.last_mut() {
+            Some(Formatted::Declaration { ending, .. }) | Some(Formatted::Other(_, ending)) if ending.is_empty() => ending.push('\n'),
+            _ => ()
+        }
+
+        let mut output = String::new();
+        let mut i = 0;
+        while i < lines.len() {
+            //Each run of declarations without anything between them is lined up together.
+            let group_end = lines[i..].iter().position(|l| !matches!(l, Formatted::Declaration { .. })).map_or(lines.len(), |p| i + p);
+            if group_end == i {
+                if let Formatted::Other(l, ending) = &lines[i] {
+                    output.push_str(l);
+                    output.push_str(ending);
+                }
+                i += 1;
+                continue
+            }
+            let group = &lines[i..group_end];
+            let head_width = group.iter().map(|l| match l { Formatted::Declaration { head, .. } => head.chars().count(), _ => 0 }).max().unwrap_or(0);
+            let code: Vec<String> = group.iter().map(|l| match l {
+                Formatted::Declaration { head, tail, .. } if !tail.is_empty() => format!("{:width$} {}", head, tail, width = head_width),
+                Formatted::Declaration { head, .. } => head.clone(),
+                _ => String::new()
+            }).collect();
+            //Comments start at the first tab stop past the longest declaration that has one.
+            let column = group.iter().zip(&code).filter(|(l, _)| matches!(l, Formatted::Declaration { comment: Some(_), .. }))
+                .map(|(_, c)| (c.chars().count() / TAB_WIDTH + 1) * TAB_WIDTH).max().unwrap_or(0);
+            for (l, c) in group.iter().zip(code) {
+                if let Formatted::Declaration { comment, ending, .. } = l {
+                    output.push_str(&c);
+                    if let Some(comment) = comment {
+                        let tabs = (column - c.chars().count() / TAB_WIDTH * TAB_WIDTH) / TAB_WIDTH;
+                        output.push_str(&"\t".repeat(tabs));
+                        output.push_str(comment);
+                    }
+                    output.push_str(ending);
+                }
+            }
+            i = group_end;
+        }
+        output
+    }
+
+    //Whether a file is already formatted.
+    pub fn check(input: &str) -> bool {
+        format(input) == input
+    }
+
+    //Format a single line of the tree.
+    fn line(node: &SyntaxNode, unit: &str, spaces: Option<usize>) -> Formatted {
+        let ending: String = node.children.iter().filter(|l| l.kind == SyntaxKind::Newline).map(|l| l.text.clone()).collect();
+        let leaves: Vec<&SyntaxNode> = node.children.iter().filter(|l| !matches!(l.kind, SyntaxKind::Indent | SyntaxKind::Newline)).collect();
+        let words: Vec<&str> = leaves.iter().filter(|l| l.kind != SyntaxKind::Whitespace).map(|l| l.text.as_str()).collect();
+        let indent = unit.repeat(indentation(node, spaces));
+        let comment = match leaves.iter().rev().find(|l| l.kind != SyntaxKind::Whitespace) {   //A comment closing the line.
+            Some(l) if l.kind == SyntaxKind::Comment => Some(l.text.clone()),
+            _ => None
+        };
+        match node.kind {
+            SyntaxKind::BlankLine => Formatted::Other(String::new(), ending),
+            SyntaxKind::CodeLine => {   //Fenced code is left exactly as it is.
+                let mut source = node.source();
+                source.truncate(source.len() - ending.len());
+                Formatted::Other(source, ending)
+            },
+            SyntaxKind::DeclarationLine if indent.is_empty() && words.len() >= 2 + comment.is_some() as usize => {
+                let rest = &words[2..words.len() - comment.is_some() as usize];
+                Formatted::Declaration { head: words[..2].join(" "), tail: rest.join(" "), comment, ending }
+            },
+            SyntaxKind::DeclarationLine | SyntaxKind::UseLine => Formatted::Other(indent + &words.join(" "), ending),
+            SyntaxKind::ExpressionLine => {
+                let mut contents = String::new();
+                let mut blocks: usize = 0;          //How many code or math blocks are open. Their insides are left as they are.
+                let mut after_separator = false;    //Whether the whitespace coming up follows a separator, and has already been written.
+                for l in &leaves {
+                    match l.kind {
+                        SyntaxKind::CodeOpen | SyntaxKind::MathOpen => blocks += 1,
+                        SyntaxKind::CodeClose | SyntaxKind::MathClose => blocks = blocks.saturating_sub(1),
+                        _ => ()
+                    }
+                    match l.kind {
+                        SyntaxKind::Separator if blocks == 0 => {   //Separators get a single space on each side.
+                            contents.truncate(contents.trim_end().len());
+                            if !contents.is_empty() {
+                                contents.push(' ');
+                            }
+                            contents.push_str(&l.text);
+                            contents.push(' ');
+                            after_separator = true;
+                            continue
+                        },
+                        SyntaxKind::Whitespace if after_separator => (),
+                        _ => contents.push_str(&l.text)
+                    }
+                    after_separator = false;
+                }
+                Formatted::Other(indent + contents.trim_end(), ending)
+            },
+            _ => {
+                let contents: String = leaves.iter().map(|l| l.text.as_str()).collect();
+                Formatted::Other(indent + contents.trim_end(), ending)
+            }
+        }
+    }
+
+    //The number of levels a line should be indented by. Tabs count as one each, and so does every run of spaces making up a level if spaces are allowed. Otherwise the lexer ignores spaces, and so does this.
+    fn indentation(node: &SyntaxNode, spaces: Option<usize>) -> usize {
+        match node.children.first() {
+            Some(l) if l.kind == SyntaxKind::Indent && node.kind != SyntaxKind::BlankLine => {
+                l.text.matches('\t').count() + spaces.map_or(0, |n| l.text.matches(' ').count() / n)
+            },
+            _ => 0
+        }
+    }
+}
+
+//Module containing the parser, a component designed to construct an abstract-syntax tree form the list of tokens.
+pub mod parser {
+    use crate::core::{tokens, parser_structs, errors};
+
+    pub fn parser(input: Vec<tokens::Token>) -> Result<Vec<parser_structs::IrElements>, errors::DiazoError> {
+        let (output, _) = parse(input, false)?;
+        Ok(output)
+    }
+
+    //The parser in recovery mode. The rest of a line is skipped after a syntax error and the parser carries on, so that every error is returned at once.
+    pub fn parser_recovering(input: Vec<tokens::Token>) -> (Vec<parser_structs::IrElements>, Vec<errors::DiazoError>) {
+        match parse(input, true) {
+            Ok(r) => r,
+            Err(e) => (Vec::new(), vec![e])   //Only reachable outside of recovery mode.
+        }
+    }
+
+    fn parse(mut input: Vec<tokens::Token>, recover: bool) -> Result<(Vec<parser_structs::IrElements>, Vec<errors::DiazoError>), errors::DiazoError> {
+
+        //Local function for building a syntax error at the token it was found at.
+        fn error_locator(kind: errors::ErrorKind, span: tokens::Span, b: tokens::Tokens, message: &str) -> errors::DiazoError {
+            errors::DiazoError::Parser { kind, file: None, span, token: b.print(), message: message.to_string() }
+        }
+
+        //Local function to reduce boilerplate when filling the tree.
+        fn tree_fill(fill_with: &tokens::Tokens, span: tokens::Span) -> (parser_structs::Tree, parser_structs::NodeId, bool, tokens::Tokens) {
+            let tree = parser_structs::Tree::new(fill_with.clone(), span);
+            let root = tree.root();
+            (tree, root, true, fill_with.clone())
+        }
+
+        //Local function to reduce boilerplate when resetting the tree.
+        fn tree_reset() -> (parser_structs::Tree, parser_structs::NodeId, bool, tokens::Tokens) {
+            let (tree, root, _, _) = tree_fill(&tokens::Tokens::Null, tokens::Span::default());
+            (tree, root, false, tokens::Tokens::Null)
+        }
+
+        //Local function that wraps a finished tree in the IrElement matching the token at its root, leaving an empty tree in its place.
+        fn tree_close(tree: &mut parser_structs::Tree) -> Option<parser_structs::IrElements> {
+            let wrap = match &tree[tree.root()].value {
+                tokens::Tokens::TypeKeyword => parser_structs::IrElements::TypeDeclaration,
+                tokens::Tokens::TypeInstance(_) => parser_structs::IrElements::TypeExpression,
+                tokens::Tokens::ContentWithFormatting(_) => parser_structs::IrElements::RawText,
+                _ => return None
             };
-            word_num = 0;                                       //Set the word counter to 0.
-            let mut line_scope_counter: usize = 0;              //Declare a local scope counter.
-            line_num += 1;                                      //Increment the line counter which will be returned in error messages.
+            let (empty, _, _, _) = tree_reset();
+            Some(wrap(std::mem::replace(tree, empty)))
+        }
+        
+        let mut output: Vec<parser_structs::IrElements> = Vec::new();   //Initialize a vector of IrElements, which represent's the parser's output.
+        let (mut tree, mut node, _, _) = tree_reset();    //Declare variables that hold the current tree and the node in it that is being added to.
+        let mut tree_contains: bool = false;                                               //Variable that stores whether or not there is something worth reading in the tree.
+        let mut token_in_tree: tokens::Tokens = tokens::Tokens::Null;   //Variable that stores what is in the tree.
+        let mut scope_parents: Vec<parser_structs::NodeId> = Vec::new();  //Stack of the type instances open at each scope. Index 0 is the root of the expression, index 1 a type instance nested one tab in, and so on.
+        let mut formatting_stack: Vec<tokens::Tokens> = Vec::new();       //Vector that carries content being pooled for formatting.                  
+        let mut previous: tokens::Tokens = tokens::Tokens::Null;            //Declare a variable to hold the previous token that was examined. Implementing look-ahead is either inefficient or requires external packages, so we will look back instead.
+        let mut previous_span: tokens::Span = tokens::Span::default();      //Where the previous token was found, which is given to its node and used for error reporting.
+        let mut formatting_span: tokens::Span = tokens::Span::default();    //Where the contents being pooled in the formatting stack start.
+        let mut line_scope: usize = 0;                                                          //Variable that stores the current line's scope counter.
+        let mut error_list: Vec<errors::DiazoError> = Vec::new();                   //Errors recorded in recovery mode.
+        let mut skipping: bool = false;                                                         //Whether the rest of a line is being skipped after an error in recovery mode.
 
-            'words: for w in l.replace("\t", " *tab! ").split_whitespace() {     //Replace hard tab characters with a keyword, iterate over separated whitespaces.
-                word_num += 1;  //Increment the word counter which will be returned in error messages.
-                match mode {
-                    Mode::Keys => {
-                        //Once that's done, get to work matching different tokens.
-                        match w {
-                            "*tab!" => {                            //Handle tabs, which are used to determine scope.
-                                if word_num == 1 || syntax_check_output.get(syntax_check_output.len() - 1) == Some(&tokens::Tokens::Tab) {
-                                    line_scope_counter += 1;                        //Increment scope counter,
-                                    syntax_check_output.push(tokens::Tokens::Tab);  //and add the tab to the tokens list only if they are at the start of the line.
+        input.push(tokens::Token { value: tokens::Tokens::Null, span: tokens::Span::default() });   //First push a null token to the end of the input since we are looking back at previous. (Would be one short otherwise).
+        for tokens::Token { value: i, span: i_span } in input { //Look through each of the tokens in the input.
+            if skipping {   //Skip everything up to the next linebreak after an error.
+                if i == tokens::Tokens::Linebreak {
+                    skipping = false;
+                    (previous, previous_span) = (i, i_span);
+                }
+                continue
+            }
+            let at: tokens::Span = previous_span;   //Where errors about the previous token are reported.
+            //Local macro for reporting an error. Outside of recovery mode this ends the parser, otherwise the error is recorded and the rest of the line skipped.
+            macro_rules! fail {
+                ($e:expr) => {{
+                    let e = $e;
+                    if !recover {
+                        return Err(e)
+                    }
+                    error_list.push(e);
+                    if let tokens::Tokens::TypeKeyword | tokens::Tokens::Assignment | tokens::Tokens::E(_) = token_in_tree {   //Half-finished declarations are dropped entirely.
+                        (tree, node, tree_contains, token_in_tree) = tree_reset();
+                    }
+                    formatting_stack.clear();
+                    skipping = i != tokens::Tokens::Linebreak;
+                    previous = if skipping { tokens::Tokens::Null } else { i };
+                    previous_span = i_span;
+                    continue
+                }};
+            }
+            match &previous {
+                tokens::Tokens::Null => {   //If there is nothing in the previous slot, move i into previous.
+                    (previous, previous_span) = (i, i_span);   //Before advancing the loop, remember to move the current i into previous.
+                },
+                //Whitespace
+                tokens::Tokens::Linebreak => {  //A linebreak ends the line before it, so this is where finished statements and expressions are closed.
+                    line_scope = 0; //Every line starts out unindented until tabs are found.
+                    if tree_contains {  //If the tree contains something, we need to determine what we are dealing with.
+                        match &token_in_tree {
+                            tokens::Tokens::Assignment | tokens::Tokens::E(_) => {  //A type declaration, which only ever spans a single line.
+                                if i == tokens::Tokens::Tab {   //Return an error, since there should be nothing nested under a type declaration statement.
+                                    fail!(error_locator(errors::ErrorKind::NestedUnderDeclaration, i_span, i.clone(), "Invalid syntax (Parser): Try removing this tab. Whitespace is functional and this tab is saying that what follows it is nested under the type declaration from the previous line."))
+                                }
+                                if tree[tree.root()].value != tokens::Tokens::TypeKeyword {  //Look back at the root of the type declaration statement.  //Check that we have reached the type keyword. If not, throw an error.
+                                    fail!(error_locator(errors::ErrorKind::InvalidTreeStructure, i_span, i.clone(), "Invalid syntax (Parser): Found declaration arguments nested under a token other than the type keyword."))
                                 }
+                                output.extend(tree_close(&mut tree));   //Push the declaration statement to the output vector.
+                                (tree, node, tree_contains, token_in_tree) = tree_reset();                //Replace the tree with a null value, set the tree_contains flag to false, and set the token_in_tree to a null token.
                             },
-                            "//" => {                                       //Line Comments
-                                mode = Mode::Comments;                      //Switch head mode to comments.
-                                syntax_check_output.push(tokens::Tokens::CommentLine);   //Push the comment symbol to the output vector.
-                            },
-                            "/*" => {                                             //Open Block Comments
-                                mode = Mode::Comments;
-                                scope_stack.push(tokens::Tokens::CommentOpen);    //Push the comment block symbol to the scope stack. 
-                                syntax_check_output.push(tokens::Tokens::CommentOpen);         // ^ Its presence stops the head from switching back to Keys mode after each line.
-                            },
-                            "*type" => {    //Keyword for declaring new types
-                                //Check if type has appeared in a scope somewhere, which it really shouldn't.
-                                if line_scope_counter != 0 {
-                                    error_locator(filename, line_num, word_num, w);
-                                    return Err("Invalid syntax: *type has been placed in a scope somewhere, it should not have been. There is no place for a type declaration in a scope. (Remove any tabs before this *type)");
-                                }
-                                scope_stack.push(tokens::Tokens::TypeKeyword);  //Push the keyword to the scope stack. 
-                                syntax_check_output.push(tokens::Tokens::TypeKeyword);       // ^ Its presence allows for handling of new names for types in the "other" arm.
-                                final_output.push(tokens::Tokens::TypeKeyword); //Push keyword into the final output which is what is visible to the calling lexer.
-                            },
-                            "=>" => {
-                                /* Check that the assignment is being used properly
-                                    It might be used without an accompanying type keyword,
-                                    or multiple words might have been put in place of the type's name being assigned */
-                                if !logic_check(&scope_stack, 0, tokens::Tokens::TypeKeyword) {
-                                    error_locator(filename, line_num, word_num, w);
-                                    return Err("Invalid syntax: Assignment operator used somewhere other than a type assignment.");
-                                } else if logic_check(&scope_stack, 0, tokens::Tokens::TypeKeyword) && !logic_check(&syntax_check_output, 1, tokens::Tokens::TypeKeyword) {
-                                    error_locator(filename, line_num, word_num - 2, w);
-                                    return Err("Invalid syntax: Type names should only be one word. Try using underscores, or check the declaration.");
-                                }
-                                scope_stack.push(tokens::Tokens::Assignment);
-                                syntax_check_output.push(tokens::Tokens::Assignment);
-                                final_output.push(tokens::Tokens::Assignment); //Push assignment symbol into the final output which is what is visible to the calling lexer.
-                            },
-                            other => {
-                                //Check if we are immediately after a type keyword but before the arrow.
-                                if logic_check(&syntax_check_output, 0, tokens::Tokens::TypeKeyword) && logic_check(&scope_stack, 0, tokens::Tokens::TypeKeyword) {
-                                    if types.contains(&other.clone().to_string()) {             //Prevent type declarations to the same name.
-                                        error_locator(filename, line_num, word_num, w);
-                                        return Err("Invalid syntax: It appears this type has been declared before, the namespace is already occupied!");
-                                    }
-                                    types.push(other.clone().to_string());                      //Add the new type as a valid option for use in future code.
-                                    syntax_check_output.push(tokens::Tokens::TypeName(other.to_string()));   //Add the token onto the output vector too.
-                                    final_output.push(tokens::Tokens::TypeName(other.to_string())); //Push new typename into the final output which is what is visible to the calling lexer.
-                                    continue
-                                }
-                                
-                                //Check if we are in the arguments section of a type declaration.
-                                if logic_check(&scope_stack, 1, tokens::Tokens::TypeKeyword) &&    //The second to last item in the scope_stack is the TypeKeyword.
-                                    logic_check(&scope_stack, 0, tokens::Tokens::Assignment) {      //The last item in the scope_stack was an assignment symbol.
-                                    if other == "any" {
-                                        syntax_check_output.push(tokens::Tokens::Any);
-                                        final_output.push(tokens::Tokens::Any); //Push keyword into the final output which is what is visible to the calling lexer.
-                                        continue
-                                    }
-                                    if types.contains(&String::from(other.clone())) {
-                                        syntax_check_output.push(tokens::Tokens::TypeAsDeclarationParameter(String::from(other)));
-                                        final_output.push(tokens::Tokens::TypeAsDeclarationParameter(String::from(other)));
-                                        continue
-                                    }
-                                    match other.clone().chars().nth(0).unwrap() {
-                                        'e' => {
-                                            let a = other.clone();  //Declare clone of other for checking around with logic.
-                                            //Make sure that there is a .. in between the e and whatever follows. Sadly we cannot check if there is an n or number yet.
-                                            if a.len() > 1 && (a.chars().nth(1).unwrap(), a.chars().nth(2).unwrap()) != ('.', '.') {
-                                                error_locator(filename, line_num, word_num, w);
-                                                return Err("Invalid syntax: Something besides \"..\" is separating the e from the number/letter here.
-                                                            Alternatively there's something else entirely following the e");
+                            tokens::Tokens::ContentWithFormatting(_) | tokens::Tokens::TypeInstance(_) | tokens::Tokens::Element => {   //Raw text or a type expression, which can continue onto indented lines.
+                                match i {
+                                    //Indented lines, blank lines and comments don't end the expression. The end of the input is handled after the loop.
+                                    tokens::Tokens::Tab | tokens::Tokens::Linebreak | tokens::Tokens::CommentLine | tokens::Tokens::CommentOpen
+                                    | tokens::Tokens::CommentContents(_) | tokens::Tokens::Null => (),
+                                    _ => {  //Anything else starts a new line at the outermost scope, so the expression is finished.
+                                        match tree_close(&mut tree) {
+                                            Some(e) => output.push(e),
+                                            None => {
+                                                fail!(error_locator(errors::ErrorKind::InvalidTreeStructure, at, i.clone(), "Invalid Syntax (Parser): Invalid tree structure... root node is neither formatting nor a type expression."))
                                             }
-                                            syntax_check_output.push(tokens::Tokens::E(other.to_string()));
-                                            final_output.push(tokens::Tokens::E(other.to_string()));
-                                            continue
-                                        },
-                                        'c' => {
-                                            let a = other.clone();  //Declare clone of other for checking around with logic.
-                                            //Make sure that there is a .. in between the c and whatever follows. Sadly we cannot check if there is an n or number yet.
-                                            if a.len() > 1 && (a.chars().nth(1).unwrap(), a.chars().nth(2).unwrap()) != ('.', '.') {
-                                                error_locator(filename, line_num, word_num, w);
-                                                return Err("Invalid syntax: Something besides \"..\" is separating the c from the number/letter here.
-                                                            Alternatively there's something else entirely following the c");
-                                            }
-                                            syntax_check_output.push(tokens::Tokens::C(other.to_string()));
-                                            final_output.push(tokens::Tokens::C(other.to_string()));
-                                            continue
-                                        },
-                                        _other => {  //The only things that can be in the arguments section are c, e, any, or a type that's been declared.
-                                        error_locator(filename, line_num, word_num, w);
-                                            return Err("Invalid syntax: Something other than a type name, \"e\", \"c\", or \"any\".
-                                                        If it is a type name, it hasn't been declared yet and so cannot be recognized.");
                                         }
+                                        (tree, node, tree_contains, token_in_tree) = tree_reset();
+                                        scope_parents.clear();
                                     }
                                 }
-                                //Check if a comment was opened but there was no space between the comment symbol and the following word. (As in this line)
-                                if other.contains("//") {
-                                    syntax_check_output.push(tokens::Tokens::CommentLine);
-                                    comments_stack.push_str(other.replace("//", "").as_str());
-                                    mode = Mode::Comments;
-                                    continue
-                                } else if other.contains("/*") {
-                                    syntax_check_output.push(tokens::Tokens::CommentOpen);
-                                    comments_stack.push_str(other.replace("/*", "").as_str());
-                                    mode = Mode::Comments;
-                                    scope_stack.push(tokens::Tokens::CommentOpen);
-                                    continue
-                                }
-                                //Otherwise, we are probably reading for types to be instantiated. Check if we're reading a type that's been declared.
-                                if types.contains(&other.clone().to_string()) {
-                                    syntax_check_output.push(tokens::Tokens::TypeInstance(other.to_string()));
-                                    mode = Mode::Contents;  //We've just identified that a type has been instantiated. This transitions the head to read for contents instead.
-                                } else {
-                                //If we can't find a type name, we are already writing content!
-                                //First we do the usual check to make sure the word doesn't end in a separator...
-                                for i in tokens::separator_list() {     //Compare it to the list of separators... only works on 2 character-long seperators.
-                                    if other.contains(i) {
-                                        contents_stack.push_str(other.replace(i, " ").as_str());    //Terminate the current contents stack.
-                                        syntax_check_output.push(tokens::Tokens::Content(contents_stack.clone()));   //Send it to the output stack.
-                                        contents_stack.clear();                                                      //Clear contents stack.
-                                        syntax_check_output.push(tokens::Tokens::Separator(String::from(i)));        //Send the separator to the output stack.
-                                        continue 'words
-                                    }
-                                }
-                                //Also check that we haven't tried to start a math or code formatting block...
-                                if other == "[[" {
-                                    syntax_check_output.push(tokens::Tokens::CodeBlockOpen);
-                                    mode = Mode::Contents;
-                                    continue 'words
-                                } else if other == "{{" {
-                                    syntax_check_output.push(tokens::Tokens::MathBlockOpen);
-                                    mode = Mode::Contents;
-                                    continue 'words
-                                }
-                                //Finally, send the word to the content stack and switch head mode to content.
-                                contents_stack.push_str(other);
-                                contents_stack.push(' ');
-                                mode = Mode::Contents;
-                                }
                             },
-                        }
-                    },
-                    Mode::Contents => {
-                        //There are two things we can encounter once we have begun reading in contents mode.
-                        //First, symbols for controlling formatting blocks.
-                        if w == "[[" {
-                            syntax_check_output.push(tokens::Tokens::Content(contents_stack.clone()));   //Interrupt the contents stack and send it to the output stack.
-                            contents_stack.clear();                                                      //Clear contents stack.
-                            syntax_check_output.push(tokens::Tokens::CodeBlockOpen);                     //Push the appropriate symbol to the output stack.
-                            continue 'words                                                              //Continue reading the contents.
-                        } else if w == "]]" {
-                            syntax_check_output.push(tokens::Tokens::Content(contents_stack.clone()));
-                            contents_stack.clear();
-                            syntax_check_output.push(tokens::Tokens::CodeBlockClose);
-                            continue 'words
-                        } else if w == "{{" {
-                            syntax_check_output.push(tokens::Tokens::Content(contents_stack.clone()));
-                            contents_stack.clear();
-                            syntax_check_output.push(tokens::Tokens::MathBlockOpen);
-                            continue 'words
-                        } else if w == "}}" {
-                            syntax_check_output.push(tokens::Tokens::Content(contents_stack.clone()));
-                            contents_stack.clear();
-                            syntax_check_output.push(tokens::Tokens::MathBlockClose);
-                            continue 'words
-                        }
-
-                        //Unfortunately, it is also possible that the user has written their text with the formatting symbols attached to words e.g. [[example
-                        if w.contains("[[") {
-                            syntax_check_output.push(tokens::Tokens::Content(contents_stack.clone()));  //Interrupt the contents stack and send it to the output stack.
-                            contents_stack.clear();                                                     //Clear the contents stack.
-                            syntax_check_output.push(tokens::Tokens::CodeBlockOpen);                    //Send the symbol to the output stack.
-                            contents_stack.push_str(w.replace("[[", "").as_str());     //Add whatever follows the symbol to the contents stack.
-                            continue 'words
-                        } else if w.contains("]]") {                  //The procedure is slightly different for the end symbols. Not only is the order
-                            for i in tokens::separator_list() { //in which the default procedure is executed different, but we must also consider the possibility of both content and a separator being attached to the symbol.
-                                if w.contains(i) {                                                                                      //Check each of the possible separators.
-                                    contents_stack.push_str(w.replace("]]", "").replace(i, " ").as_str());    //Terminate the current contents stack.
-                                    syntax_check_output.push(tokens::Tokens::Content(contents_stack.clone()));                          //Send it to the output stack.
-                                    contents_stack.clear();                                                                             //Clear contents stack.
-                                    syntax_check_output.push(tokens::Tokens::CodeBlockClose);                                           //Send the end block symbol to the output stack.
-                                    syntax_check_output.push(tokens::Tokens::Separator(String::from(i)));                               //Send the separator to the output stack.
-                                    continue 'words
-                                }
-                            }                                                                           //Procedure if there is no separator mixed in.
-                            contents_stack.push_str(w.replace("]]", " ").as_str());    //Terminate the current contents stack.
-                            syntax_check_output.push(tokens::Tokens::Content(contents_stack.clone()));  //Send it to the output stack.
-                            contents_stack.clear();                                                     //Clear contents stack.
-                            syntax_check_output.push(tokens::Tokens::CodeBlockClose);                   //Send the symbol to the output stack.
-                            continue 'words
-                        } else if w.contains("{{") {
-                            syntax_check_output.push(tokens::Tokens::Content(contents_stack.clone()));
-                            contents_stack.clear();
-                            syntax_check_output.push(tokens::Tokens::MathBlockOpen);
-                            contents_stack.push_str(w.replace("{{", "").as_str());
-                            continue 'words
-                        } else if w.contains("}}") {    //Similar procedure to the end code block symbol.
-                            for i in tokens::separator_list() {
-                                if w.contains(i) {
-                                    contents_stack.push_str(w.replace("}}", "").replace(i, " ").as_str());
-                                    syntax_check_output.push(tokens::Tokens::Content(contents_stack.clone()));
-                                    contents_stack.clear();
-                                    syntax_check_output.push(tokens::Tokens::MathBlockClose);
-                                    syntax_check_output.push(tokens::Tokens::Separator(String::from(i)));
-                                    continue 'words
-                                }
-                            }
-                            contents_stack.push_str(w.replace("]]", " ").as_str());
-                            syntax_check_output.push(tokens::Tokens::Content(contents_stack.clone()));
-                            contents_stack.clear();
-                            syntax_check_output.push(tokens::Tokens::MathBlockClose);
-                            continue 'words
-                        }
-
-                        //Alternatively, a separator, of which there are some varieties but all have the same functionality. They just look different.
-                        for i in tokens::separator_list() {
-                            if i == w {
-                                syntax_check_output.push(tokens::Tokens::Separator(w.to_string()));
-                            }
-                        }
-                        //If we aren't dealing with a separator we are loading things into the contents String. But it might have a separator attached so let's clear that up too.
-                        for i in tokens::separator_list() {     //Compare it to the list of separators... only works on 2 character-long separators.
-                            if w.contains(i) {
-                                contents_stack.push_str(w.replace(i, " ").as_str());    //Terminate the current contents stack.
-                                syntax_check_output.push(tokens::Tokens::Content(contents_stack.clone()));            //Send it to the output stack.
-                                contents_stack.clear();                                                  //Clear contents stack.
-                                syntax_check_output.push(tokens::Tokens::Separator(String::from(i)));                 //Send the separator to the output stack.
-                                continue 'words
-                            }
-                        }
-                        contents_stack.push_str(w);
-                        contents_stack.push(' ');
-                    },
-                    Mode::Comments => {
-                        //Similarly, there are only two things we can encounter once we have started reading in contents mode.
-                        //The end comment block symbol, which pops the open comment block out of the scope stack and transitions the head to keys mode.
-                        if w == "*/" {
-                            if logic_check(&scope_stack, 0, tokens::Tokens::CommentOpen) {
-                                scope_stack.pop();
-                                mode = Mode::Keys;
-                                continue
-                            } else {
-                                error_locator(filename, line_num, word_num, w);
-                                return Err("Invalid syntax: You've tried to close a block comment but this isn't a block comment!");
-                            }
-                        }
-                        //Check that the word doesn't end in the end comment block symbol, which it could if no space has been placed there.
-                        if w.contains("*/") {
-                            if logic_check(&scope_stack, 0, tokens::Tokens::CommentOpen) {
-                                comments_stack.push_str(w.replace("*/", " ").as_str()); //Terminate the current comments stack.
-                                syntax_check_output.push(tokens::Tokens::CommentContents(comments_stack.clone()));   //Send it to the output stack.
-                                comments_stack.clear();                                                 //Clear comments stack.
-                                scope_stack.pop();
-                                mode = Mode::Keys;
-                                continue
-                            } else {
-                                error_locator(filename, line_num, word_num, w);
-                                return Err("Invalid syntax: You've tried to close a block comment but this isn't a block comment!");
+                            other => {  //Otherwise return an error.
+                                fail!(error_locator(errors::ErrorKind::MisplacedToken, at, other.clone(), "Invalid syntax (Parser): This token appears to have been used out of place. The parser is attempting to place it as the root of an expression's tree."))
                             }
                         }
-                        //Alternatively, comments, which are loaded into a String.
-                        comments_stack.push_str(w);
-                        comments_stack.push(' ');
-                    },
-                }
-            }
-        }
-        //Check if the comments or contents stacks are empty or not. It there is something there, empty it out.
-        if contents_stack.len() != 0 {    //Technically these two situations should be mutually exclusive.
-            syntax_check_output.push(tokens::Tokens::Content(contents_stack));
-        }
-        if comments_stack.len() != 0 {
-            syntax_check_output.push(tokens::Tokens::CommentContents(comments_stack));
-        }
-        Ok((final_output, types))  //Since everything has been okay so far, return the output vector wrapped in Ok!
-    }
-}
-
-//Module containing the parser, a component designed to construct an abstract-syntax tree form the list of tokens.
-pub mod parser {
-    use std::{rc::Rc, cell::RefCell};
-    use crate::core::{tokens, parser_structs};
-
-    pub fn parser(mut input: Vec<tokens::Tokens>) -> Result<Vec<parser_structs::IrElements>, &'static str> {
-
-        //Local function for returning the location of a syntax error.
-        fn error_locator(a: usize, b: tokens::Tokens) {
-            eprintln!("Issue found at line {}, token:{}", a, b.print());
-        }
-
-        //Local function to reduce boilerplate when filling the tree.
-        fn tree_fill(fill_with: &tokens::Tokens) -> (Rc<RefCell<parser_structs::TreeNode>>, bool, tokens::Tokens) {
-            (Rc::new(RefCell::new(parser_structs::TreeNode::new(fill_with.clone()))), true, fill_with.clone())
-        }
-
-        //Local function to reduce boilerplate when resetting the tree.
-        fn tree_reset() -> (Rc<RefCell<parser_structs::TreeNode>>, bool, tokens::Tokens) {
-            (Rc::new(RefCell::new(parser_structs::TreeNode::new(tokens::Tokens::Null))), false, tokens::Tokens::Null)
-        }
-        
-        let mut output: Vec<parser_structs::IrElements> = Vec::new();   //Initialize a vector of IrElements, which represent's the parser's output.
-        let mut tree: Rc<RefCell<parser_structs::TreeNode>> = Rc::new(RefCell::new(parser_structs::TreeNode::new(tokens::Tokens::Null)));  //Declare a variable namespace that holds the current tree.
-        let mut tree_contains: bool = false;                                               //Variable that stores whether or not there is something worth reading in the tree.
-        let mut token_in_tree: tokens::Tokens = tokens::Tokens::Null;   //Variable that stores what is in the tree.
-        let mut formatting_stack: Vec<tokens::Tokens> = Vec::new();       //Vector that carries content being pooled for formatting.                  
-        let mut previous: tokens::Tokens = tokens::Tokens::Null;            //Declare a variable to hold the previous token that was examined. Implementing look-ahead is either inefficient or requires external packages, so we will look back instead.
-        let mut line: usize = 1;                                                                    //Initialize the line counter which will be used for error reporting.
-        let mut last_line_scope: usize;                                                 //Variable that stores the previous line's scope counter.
-        let mut line_scope: usize = 0;                                                          //Variable that stores the current line's scope counter.
-
-        input.push(tokens::Tokens::Null);   //First push a null token to the end of the input since we are looking back at previous. (Would be one short otherwise).
-        for i in input{ //Look through each of the tokens in the input.
-            match &previous {
-                tokens::Tokens::Null => {   //If there is nothing in the previous slot, move i into previous.
-                    previous = i;   //Before advancing the loop, remember to move the current i into previous.
-                },
-                //Whitespace
-                tokens::Tokens::Linebreak => {  //There are two possible outcomes when dealing with a linebreak...
-                    last_line_scope = line_scope; //But in both of them, we will move the line_scope into the last_line_scope so that we can compare against the previous line.
-                    if i == tokens::Tokens::Tab {         //First, there could be a tab after the linebreak,
-                        if tree_contains {                       //We then check if an assignment or element operator is in the tree.
-                            match token_in_tree {             //And return an error, since there should be nothing nested under a type declaration statement.
-                                tokens::Tokens::Assignment | tokens::Tokens::E(_) => {
-                                    error_locator(line + 1, i);
-                                    return Err("Invalid syntax (Parser): Try removing this tab. Whitespace is functional and this tab is saying that what follows it is nested under the type declaration from the previous line.")
-                                },
-                                tokens::Tokens::ContentWithFormatting(_) | tokens::Tokens::TypeInstance(_) => {   //Two situations that are handled similarly, either leading to raw text expression or a type expression. (These are just formatting differences.)
-                                    if line_scope >= last_line_scope && line_scope > 0 { //If the current line's scope is greater than the previous one, do nothing. We are indented and therefore still inside the same expression.
-                                    } else {
-                                        let mut switch = tree.borrow().parent.as_ref().is_some();   //Otherwise, check if the node has a parent.
-                                        while switch {
-                                            tree = parser_structs::TreeNode::back_to_parent(&tree);             //Go back to the node's parent as long as there is a parent.
-                                            switch = tree.borrow().parent.as_ref().is_some();
-                                            token_in_tree = tree.borrow().value.clone();
-                                        }
-                                        match token_in_tree {   //Since there is a possibility that we've switched the tree, check what the parent node we are dealing with is.
-                                            tokens::Tokens::ContentWithFormatting(_) => {   //Raw content, in which the content is the root of the tree (likely not a full tree in this case).
-                                                output.push(parser_structs::IrElements::RawText(Rc::clone(&tree))); //Now that there is no longer a parent node, we can push to the output vector.
-                                                (tree, tree_contains, token_in_tree) = tree_reset();
-                                            },
-                                            tokens::Tokens::TypeInstance(_) => {
-                                                output.push(parser_structs::IrElements::TypeExpression(Rc::clone(&tree)));
-                                                (tree, tree_contains, token_in_tree) = tree_reset();
-                                            },
-                                            _ => {  //Otherwise return an error.
-                                                error_locator(line, i);
-                                                return Err("Invalid Syntax (Parser): Invalid tree structure... root node is neither formatting nor a type expression.")
-                                            }
-                                        }
-                                    }
-                                },
-                                tokens::Tokens::Element => {    //We just need to go back one step without pushing anything to output yet if we have an element...
-                                    tree = parser_structs::TreeNode::back_to_parent(&tree);
-                                    token_in_tree = tree.borrow().value.clone();
-                                },
-                                _ => ()
-                            }
-                        }
-                        line += 1;  //Increment the line counter.
-                        } else {    //Alternatively, something other than a tab is there. So we must check if the tree contains something.
-                            if tree_contains {  //If it does, we need to determine what we are dealing with. We have four options...
-                                match token_in_tree {
-                                    tokens::Tokens::Assignment => { //A type declaration which does not have any elements in its definition. We need to get to the parent of the assignment symbol.
-                                        tree = parser_structs::TreeNode::back_to_parent(&tree);                                                                            //Move the tree variable namespace back to the type keyword which is the root of the type declaration statement.
-                                        if tree.borrow().value.clone() != tokens::Tokens::TypeKeyword {  //Check that we have reached the type keyword. If not, throw an error.
-                                            error_locator(line + 1, i);
-                                            return Err("Invalid syntax (Parser): Found assignment symbol nested under a token other than the type keyword.")
-                                        }
-                                        output.push(parser_structs::IrElements::TypeDeclaration(Rc::clone(&tree)));    //Push the declaration statement to the output vector.
-                                        (tree, tree_contains, token_in_tree) = tree_reset();                                                         //Replace the tree with a null value, set the tree_contains flag to false, and set the token_in_tree to a null token.
-                                    },
-                                    tokens::Tokens::E(_) => {   //Still a type declaration, but one which has elements in the definition, so the most recent node referenced in the variable is the element argument.
-                                        tree = parser_structs::TreeNode::back_to_parent(&tree);                                                     //This first "back_to_parent" moves us to the assignment symbol.
-                                        tree = parser_structs::TreeNode::back_to_parent(&tree);                                                     //This second one returns us to the desired type keyword.
-                                        if tree.borrow().value.clone() != tokens::Tokens::TypeKeyword {  //Check that we have reached the type keyword. If not, throw an error.
-                                            error_locator(line + 1, i);
-                                            return Err("Invalid syntax (Parser): Found element argument nested under a token other than the type keyword. (Type keyword not two parents away)")
-                                        }
-                                        output.push(parser_structs::IrElements::TypeDeclaration(Rc::clone(&tree)));    //Push the declaration statement to the output vector.
-                                        (tree, tree_contains, token_in_tree) = tree_reset();                                                         //Replace the tree with a null value, set the tree_contains flag to false, and set the token_in_tree to a null token.
-                                    },
-                                    tokens::Tokens::ContentWithFormatting(_) | tokens::Tokens::TypeInstance(_) => {   //Two situations that are handled similarly, either leading to raw text expression or a type expression. (These are just formatting differences.)
-                                        if line_scope > last_line_scope { //If the current line's scope is greater than the previous one, do nothing. We are indented and therefore still inside the same expression.
-                                        } else {
-                                            let mut switch = tree.borrow().parent.as_ref().is_some();   //Otherwise, check if the node has a parent.
-                                            while switch {
-                                                tree = parser_structs::TreeNode::back_to_parent(&tree);             //Go back to the node's parent as long as there is a parent.
-                                                switch = tree.borrow().parent.as_ref().is_some();
-                                                token_in_tree = tree.borrow().value.clone();
-                                            }
-                                            match token_in_tree {   //Since there is a possibility that we've switched the tree, check what the parent node we are dealing with is.
-                                                tokens::Tokens::ContentWithFormatting(_) => {   //Raw content, in which the content is the root of the tree (likely not a full tree in this case).
-                                                    output.push(parser_structs::IrElements::RawText(Rc::clone(&tree))); //Now that there is no longer a parent node, we can push to the output vector.
-                                                    (tree, tree_contains, token_in_tree) = tree_reset();
-                                                },
-                                                tokens::Tokens::TypeInstance(_) => {
-                                                    output.push(parser_structs::IrElements::TypeExpression(Rc::clone(&tree)));
-                                                    (tree, tree_contains, token_in_tree) = tree_reset();
-                                                },
-                                                _ => {  //Otherwise return an error.
-                                                    error_locator(line, i);
-                                                    return Err("Invalid Syntax (Parser): Invalid tree structure... root node is neither formatting nor a type expression.")
-                                                }
-                                            }
-                                        }
-                                    },
-                                    tokens::Tokens::Element => {    //We just need to go back one step without pushing anything to output yet if we have an element...
-                                        tree = parser_structs::TreeNode::back_to_parent(&tree);
-                                        token_in_tree = tree.borrow().value.clone();
-                                    },
-                                    other => {  //Otherwise return an error.
-                                        error_locator(line, other);
-                                        return Err("Invalid syntax (Parser): The above token appears to have been used out of place. The parser is attempting to place it as the root of an expression's tree.")
-                                    }
-                                }
-                            }
-                            line += 1;  //Increment the line counter.
-                        }
-                    previous = i;
+                    }
+                    (previous, previous_span) = (i, i_span);
                 },
                 tokens::Tokens::Tab => {    //Tabs are used for spawning the "element" token that elements of collections are nested under.
-                    if i == tokens::Tokens::Tab {   //Check if the next element is a tab. If it is, only increment the scope counter.
-                        line_scope += 1;
-                        previous = i;   //Before advancing the loop, remember to move the current i into previous.
+                    line_scope += 1;    //Each tab at the start of a line nests it one scope deeper.
+                    //Keep counting if the next token is another tab. Indented blank lines and comments don't do anything.
+                    if let tokens::Tokens::Tab | tokens::Tokens::Linebreak | tokens::Tokens::CommentLine | tokens::Tokens::CommentOpen | tokens::Tokens::Null = i {
+                        (previous, previous_span) = (i, i_span);   //Before advancing the loop, remember to move the current i into previous.
                         continue
-                    } else if tree_contains {   //Otherwise, check if the tree is empty. If not, increment the scope counter and then do something else!
-                        //Either the tree is holding a type instance, or it is holding content with formatting.
-                        if let tokens::Tokens::TypeInstance(_) = token_in_tree {  //Only spawn the element token for the type instances. Content with formatting will hold type instances directly under themselves.
-                            tree = parser_structs::TreeNode::add_and_set(tokens::Tokens::Element, &tree);   //Spawn an element and make it the node that is being held in the tree variable.
-                            token_in_tree = tokens::Tokens::Element;                                                                     //Update the token_in_tree.
-                        } 
                     }
-                    line_scope += 1;
-                    previous = i;   //Before advancing the loop, remember to move the current i into previous.
+                    if !tree_contains { //There has to be an expression for the line to be nested under.
+                        fail!(error_locator(errors::ErrorKind::IndentedOutsideExpression, i_span, i.clone(), "Invalid syntax (Parser): Indented line found outside of any expression. There is nothing for it to be nested under."))
+                    }
+                    let root_value = tree[tree.root()].value.clone();
+                    if let tokens::Tokens::ContentWithFormatting(_) = root_value {  //Content with formatting will hold whatever is nested under it directly.
+                        node = tree.root();
+                        token_in_tree = root_value;
+                    } else {    //Type instances get a new element to hold the line, placed under the type instance one scope out.
+                        scope_parents.truncate(line_scope); //Forget about type instances from deeper lines, which have ended now that we're back out here.
+                        if scope_parents.len() < line_scope {
+                            fail!(error_locator(errors::ErrorKind::OverIndented, i_span, i.clone(), "Invalid syntax (Parser): This line is indented further than the expression above it allows. Only type expressions can have lines nested under them."))
+                        }
+                        node = tree.add(tokens::Tokens::Element, previous_span, scope_parents[line_scope - 1]);    //Spawn an element and make it the node that is being held in the tree variable.
+                        token_in_tree = tokens::Tokens::Element;                                                                    //Update the token_in_tree.
+                    }
+                    (previous, previous_span) = (i, i_span);   //Before advancing the loop, remember to move the current i into previous.
                 },
                 //Type Declaration Statements
                 tokens::Tokens::TypeKeyword => {    //Type Keyword. It will become the root of a tree for a type declaration statement.
                     if tree_contains {  //If a tree already exists, something is wrong. Type keywords don't go in other expressions.
-                        error_locator(line, previous.clone());
-                        return Err("Invalid Syntax (Parser): Type keyword was found in another expression or statement. It cannot be here.")
+                        fail!(error_locator(errors::ErrorKind::MisplacedTypeKeyword, at, previous.clone(), "Invalid Syntax (Parser): Type keyword was found in another expression or statement. It cannot be here."))
                     } else {    //Otherwise start a new tree with the type keyword as the root.
-                    (tree, tree_contains, token_in_tree) = tree_fill(&previous.clone());    //Update tree_contains and token_in_tree appropriately.
+                    (tree, node, tree_contains, token_in_tree) = tree_fill(&previous.clone(), previous_span);    //Update tree_contains and token_in_tree appropriately.
                     }
-                    previous = i;   //Before advancing the loop, remember to move the current i into previous.
+                    (previous, previous_span) = (i, i_span);   //Before advancing the loop, remember to move the current i into previous.
                 },
                 tokens::Tokens::TypeName(s) => {    //Name of a type which is being declared. This will be the first child of a type keyword.
                     if tree_contains {  //Check that a tree exists.
                         if let tokens::Tokens::TypeKeyword = token_in_tree {   //Ensure that the value in the tree is a type keyword.
-                            parser_structs::TreeNode::add(tokens::Tokens::TypeName(s.clone()), &tree);    //Add the type name to the tree.
-                            previous = i;   //Before advancing the loop, remember to move the current i into previous.
+                            tree.add(tokens::Tokens::TypeName(s.clone()), previous_span, node);    //Add the type name to the tree.
+                            (previous, previous_span) = (i, i_span);   //Before advancing the loop, remember to move the current i into previous.
                         } else {    //Return an error otherwise.
-                            error_locator(line, previous.clone());
-                            return Err("Invalid Syntax (Parser): Type name was not nested directly under a type keyword.")
+                            fail!(error_locator(errors::ErrorKind::MisplacedDeclarationArgument, at, previous.clone(), "Invalid Syntax (Parser): Type name was not nested directly under a type keyword."))
                         }
                     } else {  //Return if no tree exists.
-                        error_locator(line, previous.clone());
-                        return Err("Invalid Syntax (Parser): Type name was not found in a type declaration.")
+                        fail!(error_locator(errors::ErrorKind::MisplacedDeclarationArgument, at, previous.clone(), "Invalid Syntax (Parser): Type name was not found in a type declaration."))
                     }
                 },
                 tokens::Tokens::Assignment => { //The assignment symbol. Should be the second child of a type keyword.
                     if tree_contains {  //Check that a tree exists.
                         if let tokens::Tokens::TypeKeyword = token_in_tree {    //Ensure that the tree's current node is a type keyword.
-                            tree = parser_structs::TreeNode::add_and_set(tokens::Tokens::Assignment, &tree);    //Add the assignment symbol as a child of the type keyword, then shift the tree to hold the assignment symbol's node.
+                            node = tree.add(tokens::Tokens::Assignment, previous_span, node);    //Add the assignment symbol as a child of the type keyword, then shift the tree to hold the assignment symbol's node.
                             token_in_tree = tokens::Tokens::Assignment;     //Update the token_in_tree value to show that an assignment symbol is now the node at the head.
-                            previous = i;   //Before advancing the loop, remember to move the current i into previous.
+                            (previous, previous_span) = (i, i_span);   //Before advancing the loop, remember to move the current i into previous.
                         } else {    //Return an error otherwise.
-                            error_locator(line, previous.clone());
-                            return Err("Invalid Syntax (Parser): Assignment symbol was not nested directly under a type keyword.")
+                            fail!(error_locator(errors::ErrorKind::MisplacedDeclarationArgument, at, previous.clone(), "Invalid Syntax (Parser): Assignment symbol was not nested directly under a type keyword."))
                         }
                     } else {  //Return if no tree exists.
-                        error_locator(line, previous.clone());
-                        return Err("Invalid Syntax (Parser): Assignment symbol was placed outside of a type declaration.")
+                        fail!(error_locator(errors::ErrorKind::MisplacedDeclarationArgument, at, previous.clone(), "Invalid Syntax (Parser): Assignment symbol was placed outside of a type declaration."))
                     }
                 },
                 tokens::Tokens::C(s) => {   //Content argument, of a type declaration statement.
                     if tree_contains {  //First confirm that a tree exists.
                         if let tokens::Tokens::Assignment | tokens::Tokens::E(_) = token_in_tree {  //A "c" can follow the assignment symbol or the element "e" argument
-                        parser_structs::TreeNode::add(tokens::Tokens::C(s.clone()), &tree); //Add the token to the tree.
-                            previous = i;   //Before advancing the loop, remember to move the current i into previous.
+                        tree.add(tokens::Tokens::C(s.clone()), previous_span, node); //Add the token to the tree.
+                            (previous, previous_span) = (i, i_span);   //Before advancing the loop, remember to move the current i into previous.
                         } else {    //Return an error otherwise.
-                            error_locator(line, previous.clone());
-                            return Err("Invalid Syntax (Parser): Content argument nested under wrong token. (Nested on something other than assignment symbol or element argument.")
+                            fail!(error_locator(errors::ErrorKind::MisplacedDeclarationArgument, at, previous.clone(), "Invalid Syntax (Parser): Content argument nested under wrong token. (Nested on something other than assignment symbol or element argument."))
                         }
                     } else {    //Return an error if no tree is found.
-                        error_locator(line, previous.clone());
-                        return Err("Invalid Syntax (Parser): Content argument placed outside of a type declaration.")
+                        fail!(error_locator(errors::ErrorKind::MisplacedDeclarationArgument, at, previous.clone(), "Invalid Syntax (Parser): Content argument placed outside of a type declaration."))
                     }
                 },
                 tokens::Tokens::E(s) => {   //Element argument of a type declaration statement.
                     if tree_contains {  //First confirm that a tree exists.
                         if let tokens::Tokens::Assignment = token_in_tree { //An "e" can only follow the assignment symbol.
-                            tree = parser_structs::TreeNode::add_and_set(tokens::Tokens::E(s.clone()), &tree);  //Add the token to the tree and make it the node stored in the namespace.
+                            node = tree.add(tokens::Tokens::E(s.clone()), previous_span, node);  //Add the token to the tree and make it the node stored in the namespace.
                             token_in_tree = tokens::Tokens::E(s.clone());
-                            previous = i;   //Before advancing the loop, remember to move the current i into previous.
+                            (previous, previous_span) = (i, i_span);   //Before advancing the loop, remember to move the current i into previous.
                         } else {    //Return error if nested under another token.
-                            error_locator(line, previous.clone());
-                            return Err("Invalid Syntax (Parser): Element argument nested under wrong token. Can only be placed under an assignment symbol.")
+                            fail!(error_locator(errors::ErrorKind::MisplacedDeclarationArgument, at, previous.clone(), "Invalid Syntax (Parser): Element argument nested under wrong token. Can only be placed under an assignment symbol."))
                         }
                     } else {    //Return error if no tree found.
-                        error_locator(line, previous.clone());
-                        return Err("Invalid Syntax (Parser): Element argument placed outside of a type declaration.")
+                        fail!(error_locator(errors::ErrorKind::MisplacedDeclarationArgument, at, previous.clone(), "Invalid Syntax (Parser): Element argument placed outside of a type declaration."))
                     }
                 },
                 tokens::Tokens::Any =>  {   //Any argument of a type declaration statement.
                     if tree_contains {  //First confirm that a tree exists.
                         if let tokens::Tokens::Assignment | tokens::Tokens::E(_) = token_in_tree {  //Any can be placed after either the assignment symbol of the element argument.
-                            parser_structs::TreeNode::add(tokens::Tokens::Any, &tree);  //Add the token to the tree.
-                            previous = i;   //Move current i into previous.
+                            tree.add(tokens::Tokens::Any, previous_span, node);  //Add the token to the tree.
+                            (previous, previous_span) = (i, i_span);   //Move current i into previous.
                         } else {    //Return error if found nested under the wrong token.
-                            error_locator(line, previous.clone());
-                            return Err("Invalid Syntax (Parser): \"Any\" argument nested under wrong token. Can only be under either the assignment symbol or element argument.")
+                            fail!(error_locator(errors::ErrorKind::MisplacedDeclarationArgument, at, previous.clone(), "Invalid Syntax (Parser): \"Any\" argument nested under wrong token. Can only be under either the assignment symbol or element argument."))
                         }
                     } else {    //Return error if no tree found.
-                        error_locator(line, previous.clone());
-                        return Err("Invalid Syntax (Parser): \"Any\" argument placed outside of a type declaration.")
+                        fail!(error_locator(errors::ErrorKind::MisplacedDeclarationArgument, at, previous.clone(), "Invalid Syntax (Parser): \"Any\" argument placed outside of a type declaration."))
                     }
                 },
                 tokens::Tokens::TypeAsDeclarationParameter(s) => {  //An argument of a type declaration statement that allows for type expressions to be nested in on another.
                     if tree_contains {  //First confirm that a tree exists.
                         if let tokens::Tokens::Assignment | tokens::Tokens::E(_) = token_in_tree {  //Can be placed after either the assignment symbol of the element argument.
-                            parser_structs::TreeNode::add(tokens::Tokens::TypeAsDeclarationParameter(s.clone()), &tree);      //Add the token to the tree.
-                            previous = i;   //Move current i into previous.
+                            tree.add(tokens::Tokens::TypeAsDeclarationParameter(s.clone()), previous_span, node);      //Add the token to the tree.
+                            (previous, previous_span) = (i, i_span);   //Move current i into previous.
                         } else {    //Return error if found nested under the wrong token.
-                            error_locator(line, previous.clone());
-                            return Err("Invalid Syntax (Parser): Nested type argument nested under wrong token. Can only be under either the assignment symbol or element argument.")
+                            fail!(error_locator(errors::ErrorKind::MisplacedDeclarationArgument, at, previous.clone(), "Invalid Syntax (Parser): Nested type argument nested under wrong token. Can only be under either the assignment symbol or element argument."))
                         }
                     } else {    //Return error if no tree found.
-                        error_locator(line, previous.clone());
-                        return Err("Invalid Syntax (Parser): Nested type argument placed outside of a type declaration.")
+                        fail!(error_locator(errors::ErrorKind::MisplacedDeclarationArgument, at, previous.clone(), "Invalid Syntax (Parser): Nested type argument placed outside of a type declaration."))
+                    }
+                },
+                tokens::Tokens::Annotation(s) => {  //An annotation on a type declaration statement. These are kept under the type keyword rather than among the arguments.
+                    if tree_contains {  //First confirm that a tree exists.
+                        if let tokens::Tokens::Assignment | tokens::Tokens::E(_) = token_in_tree {  //Can be placed after either the assignment symbol or the element argument.
+                            tree.add(tokens::Tokens::Annotation(s.clone()), previous_span, tree.root());  //Add the token to the root of the tree.
+                            (previous, previous_span) = (i, i_span);   //Move current i into previous.
+                        } else {    //Return error if found nested under the wrong token.
+                            fail!(error_locator(errors::ErrorKind::MisplacedDeclarationArgument, at, previous.clone(), "Invalid Syntax (Parser): Annotation placed before the assignment symbol of a type declaration."))
+                        }
+                    } else {    //Return error if no tree found.
+                        fail!(error_locator(errors::ErrorKind::MisplacedDeclarationArgument, at, previous.clone(), "Invalid Syntax (Parser): Annotation placed outside of a type declaration."))
                     }
                 },
                 //Type Instances
                 tokens::Tokens::TypeInstance(s) => {    //Instantiating a type.
                     if tree_contains {  //Check if a tree exists.
                         if let tokens::Tokens::Element | tokens::Tokens::ContentWithFormatting(_) = token_in_tree { //We can either place this under an element or contentWithFormatting token.
-                            tree = parser_structs::TreeNode::add_and_set(tokens::Tokens::TypeInstance(s.clone()), &tree);   //Add and shift the tree.
+                            if let tokens::Tokens::Element = token_in_tree {  //Type instances in elements can have lines nested under them in turn.
+                                node = tree.add(tokens::Tokens::TypeInstance(s.clone()), previous_span, node);   //Add and shift the tree.
+                                scope_parents.push(node);
+                            } else {
+                                node = tree.add(tokens::Tokens::TypeInstance(s.clone()), previous_span, node);
+                            }
                             token_in_tree = tokens::Tokens::TypeInstance(s.clone());    //Update the token_in_tree.
                         } else if let tokens::Tokens::TypeInstance(_) = token_in_tree { //Specific error message for nesting directly under another type.
-                            error_locator(line, previous.clone());
-                            return Err("Invalid Syntax (Parser): The name of the type does not need to be included in the same line as the parent type expression. The parser will automatically pattern match nested type expressions in line (e.g. anything before the elements of the colleciton).")
+                            fail!(error_locator(errors::ErrorKind::MisplacedTypeExpression, at, previous.clone(), "Invalid Syntax (Parser): The name of the type does not need to be included in the same line as the parent type expression. The parser will automatically pattern match nested type expressions in line (e.g. anything before the elements of the colleciton)."))
                         } else if let tokens::Tokens::TypeKeyword | tokens::Tokens::Assignment | tokens::Tokens::E(_) = token_in_tree { //Specific error message for nesting under a type declaration.
-                            error_locator(line, previous.clone());
-                            return Err("Invalid Syntax (Parser): Type expressions cannot be nested under type declarations.")
+                            fail!(error_locator(errors::ErrorKind::MisplacedTypeExpression, at, previous.clone(), "Invalid Syntax (Parser): Type expressions cannot be nested under type declarations."))
                         } else {    //Generic error message.
-                            error_locator(line, previous.clone());
-                            return Err("Invalid Syntax (Parser): Attempted to generate a type expression somewhere that one can't be placed.")
+                            fail!(error_locator(errors::ErrorKind::MisplacedTypeExpression, at, previous.clone(), "Invalid Syntax (Parser): Attempted to generate a type expression somewhere that one can't be placed."))
                         }
                     } else {    //Otherwise, create one.
-                        (tree, tree_contains, token_in_tree) = tree_fill(&tokens::Tokens::TypeInstance(s.clone())); //Create a new tree, updating the relevant variables.
+                        (tree, node, tree_contains, token_in_tree) = tree_fill(&tokens::Tokens::TypeInstance(s.clone()), previous_span); //Create a new tree, updating the relevant variables.
+                        scope_parents = vec![node];                                                     //The new type instance is the outermost scope.
                     }
-                    previous = i;   //Increment the line counter.
+                    (previous, previous_span) = (i, i_span);   //Increment the line counter.
                 },
                 tokens::Tokens::Content(s) => {
+                    if formatting_stack.is_empty() {
+                        formatting_span = previous_span;
+                    }
                     match i {   //Check what the next token is. We need to know if it is more content that's going to get thrown in the same contentformatting block or a separator or other token, in which case we would be done immediately and package this and anything else in the content stack into a contentformatting block.
-                        tokens::Tokens::CodeBlockOpen | tokens::Tokens::MathBlockOpen => {  //If it is open code block, open math block, do nothing, and push to stack.
+                        tokens::Tokens::CodeBlockOpen | tokens::Tokens::MathBlockOpen | tokens::Tokens::CodeFence(_) => {  //If it is open code block, open math block or code fence, do nothing, and push to stack.
                             formatting_stack.push(previous.clone());
                         },
                         tokens::Tokens::CodeBlockClose | tokens::Tokens::MathBlockClose => {    //If it is a close code block or math block, we would repackage this into the appropriate block and push to stack.
@@ -1063,71 +1468,432 @@ pub mod parser {
                         _ => {  //Anything else leads to previous being immediately placed in the wrapping content formatting block.
                             formatting_stack.push(previous.clone());
                             if tree_contains {  //We then check what we need to do based on whether or not there is a tree.
-                                parser_structs::TreeNode::add(tokens::Tokens::ContentWithFormatting(formatting_stack.clone()), &tree);  //If there is, add to it.
+                                tree.add(tokens::Tokens::ContentWithFormatting(formatting_stack.clone()), formatting_span, node);  //If there is, add to it.
                                 formatting_stack = Vec::new();  //Reset the formatting_stack vector.
                             } else {    //If there is no tree, make one.
-                                (tree, tree_contains, token_in_tree) = tree_fill(&tokens::Tokens::ContentWithFormatting(formatting_stack.clone()));
+                                (tree, node, tree_contains, token_in_tree) = tree_fill(&tokens::Tokens::ContentWithFormatting(formatting_stack.clone()), formatting_span);
                                 formatting_stack = Vec::new();  //Reset the formatting_stack vector.
                             }
                         }
                     }
-                    previous = i;   //Reassign previous.
+                    (previous, previous_span) = (i, i_span);   //Reassign previous.
+                },
+                tokens::Tokens::CodeFence(_) => {  //A fenced code block opens here. The fence is pooled with the block it holds so that the language tag isn't lost.
+                    if formatting_stack.is_empty() {
+                        formatting_span = previous_span;
+                    }
+                    formatting_stack.push(previous.clone());
+                    (previous, previous_span) = (i, i_span);
                 },
-                tokens::Tokens::CodeBlockClose | tokens::Tokens::MathBlockClose => {    //These would be skipped but must have the following token checked since it is possible that a separator follows them, requiring the formatting stack to be packaged.
-                    if let tokens::Tokens::Content(_) | tokens::Tokens::CodeBlockOpen | tokens::Tokens::MathBlockOpen = i { }   //Do nothing in these cases.
+                tokens::Tokens::CodeBlockClose | tokens::Tokens::MathBlockClose | tokens::Tokens::CodeBlock(_) => {    //These would be skipped but must have the following token checked since it is possible that a separator follows them, requiring the formatting stack to be packaged.
+                    if let tokens::Tokens::CodeBlock(_) = previous {    //Fenced code blocks come out of the lexer whole, so they go straight on the stack.
+                        formatting_stack.push(previous.clone());
+                    }
+                    if let tokens::Tokens::Content(_) | tokens::Tokens::CodeBlockOpen | tokens::Tokens::MathBlockOpen | tokens::Tokens::CodeFence(_) = i { }   //Do nothing in these cases.
                     else {  //Wrap in the contentwithformatting and push to the tree, or create a new tree.
                         if tree_contains {  //Add to an existing tree.
-                            parser_structs::TreeNode::add(tokens::Tokens::ContentWithFormatting(formatting_stack.clone()), &tree);
+                            tree.add(tokens::Tokens::ContentWithFormatting(formatting_stack.clone()), formatting_span, node);
                             formatting_stack = Vec::new();
                         } else {    //Create a new tree.
-                            (tree, tree_contains, token_in_tree) = tree_fill(&tokens::Tokens::ContentWithFormatting(formatting_stack.clone()));
+                            (tree, node, tree_contains, token_in_tree) = tree_fill(&tokens::Tokens::ContentWithFormatting(formatting_stack.clone()), formatting_span);
                             formatting_stack = Vec::new();
                         }
                     }
-                    previous = i;      //Reassign previous.
+                    (previous, previous_span) = (i, i_span);      //Reassign previous.
                 },
                 //Tokens that can be ignored by the parser.
                 tokens::Tokens::Separator(_) | tokens::Tokens::UseKeyword | tokens::Tokens::Filename(_) | tokens::Tokens::CommentLine | tokens::Tokens::CommentOpen
                 | tokens::Tokens::CommentContents(_) | tokens::Tokens::CodeBlockOpen | tokens::Tokens::MathBlockOpen => {
-                    previous = i;   //Do this and nothing else.
+                    (previous, previous_span) = (i, i_span);   //Do this and nothing else.
                 },
                 //Illegal Tokens. These are made by the parser but should not be found by the parser when parsing the lexer's output. The lexer cannot make these. Throw errors for all of them.
-                tokens::Tokens::Element | tokens::Tokens::ContentWithFormatting(_) | tokens::Tokens::CodeBlock(_) | tokens::Tokens::MathBlock(_) => {
-                    error_locator(line, previous.clone());
-                    return Err("Parser error: Parser found tokens which cannot be created by the lexer.")
+                tokens::Tokens::Element | tokens::Tokens::ContentWithFormatting(_) | tokens::Tokens::MathBlock(_) => {
+                    fail!(error_locator(errors::ErrorKind::IllegalToken, at, previous.clone(), "Parser error: Parser found tokens which cannot be created by the lexer."))
                 }
             }
         }
-        Ok(output)
+        if tree_contains {  //The input may end in the middle of a statement or expression without a linebreak, so close whatever is left in the tree.
+            match tree_close(&mut tree) {
+                Some(e) => output.push(e),
+                None => {
+                    let e = error_locator(errors::ErrorKind::InvalidTreeStructure, previous_span, token_in_tree, "Invalid Syntax (Parser): Invalid tree structure... root node is neither formatting nor a type expression.");
+                    if !recover {
+                        return Err(e)
+                    }
+                    error_list.push(e);
+                }
+            }
+        }
+        Ok((output, error_list))
     }
 }
 
 //Module containing the interpreter. The declaration statements' trees are converted into abstract types, and expressions' trees are then pattern matched and validated against those before the creation of the actual objects.
 pub mod interpreter {
-    use std::{rc::Rc, cell::RefCell};
-    use crate::core::{tokens, parser_structs, interpreter_structs};
+    use crate::core::{tokens, parser_structs, interpreter_structs, errors};
+    use crate::core::interpreter_structs::{Count, DeclarationParameter, DiazoObject, Field, TypeRegistry};
 
-    pub fn interpreter(input: Vec<parser_structs::IrElements>) -> Vec<interpreter_structs::DiazoObject> {
+    pub fn interpreter(input: Vec<parser_structs::IrElements>) -> Result<Vec<DiazoObject>, Vec<errors::DiazoError>> {
 
-        let mut output: Vec<interpreter_structs::DiazoObject> = Vec::new();
-        let mut tree: Rc<RefCell<parser_structs::TreeNode>>;    //The tree being analyzed by the interpreter.
+        //Local function for building a type error found at a span within an expression, which is written out along with it.
+        fn error_locator(kind: errors::ErrorKind, a: parser_structs::Node, span: tokens::Span, message: &str) -> errors::DiazoError {
+            errors::DiazoError::Interpreter { kind, file: None, span, expression: describe_expression(a), message: message.to_string() }
+        }
+
+        let mut output: Vec<DiazoObject> = Vec::new();
+        let mut types: TypeRegistry = TypeRegistry::new();          //The types declared so far, which expressions are matched against.
+        let mut error_list: Vec<errors::DiazoError> = Vec::new();   //Problems found so far. Every expression is checked before giving up.
+
+        for i in input {
+            let mut issues: Vec<(tokens::Span, String)> = Vec::new();   //Each mismatch, along with the part of the expression it was found at.
+            let tree = match i {
+                parser_structs::IrElements::TypeDeclaration(tree) => {
+                    if let Err(e) = declare(&mut types, &tree) {
+                        error_list.push(e);
+                    }
+                    continue
+                },
+                parser_structs::IrElements::TypeExpression(tree) => {
+                    output.push(build_expression(tree.node(tree.root()), &types, &mut issues));
+                    tree
+                },
+                parser_structs::IrElements::RawText(tree) => {
+                    output.push(build_raw_text(tree.node(tree.root()), &types, &mut issues));
+                    tree
+                }
+            };
+            for (span, j) in &issues {
+                error_list.push(error_locator(errors::ErrorKind::TypeMismatch, tree.node(tree.root()), *span, j));
+            }
+        }
+        if !error_list.is_empty() {
+            return Err(error_list)
+        }
+        Ok(output)
+    }
 
+    //Collect only the type declarations from the parser's output, e.g. to see every type available after a *use.
+    pub fn registry(input: &[parser_structs::IrElements]) -> Result<TypeRegistry, errors::DiazoError> {
+        let mut types: TypeRegistry = TypeRegistry::new();
         for i in input {
-            //Unwrap the tree stored in the IrElement.
-            tree = i.unwrap().unwrap();
+            if let parser_structs::IrElements::TypeDeclaration(tree) = i {
+                declare(&mut types, tree)?;
+            }
+        }
+        Ok(types)
+    }
+
+    //Turn a type declaration statement's tree into an abstract type and add it to the registry.
+    fn declare(types: &mut TypeRegistry, tree: &parser_structs::Tree) -> Result<(), errors::DiazoError> {
+        let root = tree.node(tree.root());
+        let result = interpreter_structs::AbstractDType::new(root).and_then(|t| types.declare(t));
+        result.map_err(|e| {
+            let name = root.children().find_map(|c| match c.value() {
+                tokens::Tokens::TypeName(s) => Some(s.clone()),
+                _ => None
+            });
+            errors::DiazoError::Interpreter {
+                kind: errors::ErrorKind::InvalidDeclaration,
+                file: None,
+                span: root.span(),
+                expression: "*type ".to_string() + name.unwrap_or_default().as_str(),
+                message: e.to_string()
+            }
+        })
+    }
+
+    //Pattern match a type expression's tree against the declaration of its type and build the object it describes, recording every mismatch that is found.
+    fn build_expression(node: parser_structs::Node, types: &TypeRegistry, issues: &mut Vec<(tokens::Span, String)>) -> DiazoObject {
+        let name = match node.value() {
+            tokens::Tokens::TypeInstance(s) => s.clone(),
+            other => {
+                issues.push((node.span(), format!("Expected a type instance at the root of the expression, found: {}", other.print())));
+                return DiazoObject::default()
+            }
+        };
+        let mut object = DiazoObject { type_name: Some(name.clone()), ..Default::default() };
+        let dtype = match types.get(&name) {
+            Some(t) => t,
+            None => {
+                issues.push((node.span(), format!("The type \"{}\" has not been declared.", name)));
+                return object
+            }
+        };
+        let mut n: Option<usize> = None;   //Value of the variable count "n", which has to be the same everywhere in a single expression.
+
+        //First the header, which is every piece of content placed directly under the type instance.
+        let fields = fields_of(node);
+        match arity(&dtype.header, types) {
+            Ok(a) => if let Err(e) = match_arity(fields.len(), a, &mut n) {
+                issues.push((node.span(), format!("Arity mismatch in the header of \"{}\" (declared as \"{}\"): {}", name, dtype.signature(), e)));
+            },
+            Err(e) => issues.push((node.span(), e))
+        }
+        (object.label, object.contents) = resolve(&dtype.header, fields, n, types);
+
+        //Then the elements, each of which sits under an element node.
+        let elements: Vec<_> = node.children().filter(|c| *c.value() == tokens::Tokens::Element).collect();
+        let spec = match &dtype.elements {
+            Some(spec) => spec,
+            None => {
+                if !elements.is_empty() {
+                    issues.push((elements[0].span(), format!("\"{}\" is not a collection, but {} element(s) were nested under it.", name, elements.len())));
+                }
+                return object
+            }
+        };
+        if let Count::Fixed(count) = spec.count {   //An element argument like "e..3" fixes the number of elements.
+            if count != elements.len() {
+                issues.push((node.span(), format!("\"{}\" takes exactly {} element(s), but {} were found.", name, count, elements.len())));
+            }
+        }
+        for (index, element) in elements.iter().enumerate() {
+            let nested = element.children().find(|c| matches!(c.value(), tokens::Tokens::TypeInstance(_)));
+            match nested {
+                Some(instance) => {     //An explicit type expression in the element. Only "any" or the matching type can hold one.
+                    let allowed = match (spec.parameters.as_slice(), instance.value()) {
+                        ([DeclarationParameter::Any], _) => true,
+                        ([DeclarationParameter::Type(t)], tokens::Tokens::TypeInstance(s)) => t == s,
+                        _ => false
+                    };
+                    if allowed {
+                        object.elements.push(build_expression(instance, types, issues));
+                    } else {
+                        issues.push((instance.span(), format!("Element {} of \"{}\" should be {}, but a \"{}\" expression was found.",
+                            index + 1, name, describe_parameters(&spec.parameters), describe_expression(instance))));
+                    }
+                },
+                None => {   //Inline content, which is matched against the arguments following the element argument.
+                    let fields = fields_of(*element);
+                    if let [DeclarationParameter::Any] = spec.parameters.as_slice() {   //Anything goes, so the element is kept as plain content.
+                        object.elements.push(DiazoObject { contents: fields, ..Default::default() });
+                        continue
+                    }
+                    match arity(&spec.parameters, types) {
+                        Ok(a) => if let Err(e) = match_arity(fields.len(), a, &mut n) {
+                            issues.push((element.span(), format!("Arity mismatch in element {} of \"{}\" (elements declared as {}): {}", index + 1, name, describe_parameters(&spec.parameters), e)));
+                        },
+                        Err(e) => {
+                            issues.push((element.span(), e));
+                            return object
+                        }
+                    }
+                    let mut e = DiazoObject::default();
+                    let parameters = match spec.parameters.as_slice() {
+                        [DeclarationParameter::Type(t)] => match types.get(t) {    //A single type written inline, like the definitions of an lsdef, becomes an object of that type.
+                            Some(t) => {
+                                e.type_name = Some(t.name.clone());
+                                t.header.as_slice()
+                            },
+                            None => spec.parameters.as_slice()
+                        },
+                        other => other
+                    };
+                    (e.label, e.contents) = resolve(parameters, fields, n, types);
+                    object.elements.push(e);
+                }
+            }
+        }
+        object
+    }
+
+    //Build the object for a line of raw text. Whatever is nested under it is kept too.
+    fn build_raw_text(node: parser_structs::Node, types: &TypeRegistry, issues: &mut Vec<(tokens::Span, String)>) -> DiazoObject {
+        let mut object = DiazoObject::default();
+        if let tokens::Tokens::ContentWithFormatting(v) = node.value() {
+            object.contents.push(Field::new(v));
+        }
+        object.contents.extend(fields_of(node));
+        for i in node.children() {
+            if let tokens::Tokens::TypeInstance(_) = i.value() {
+                object.elements.push(build_expression(i, types, issues));
+            }
+        }
+        object
+    }
+
+    //Collect the content fields placed directly under a node.
+    fn fields_of(node: parser_structs::Node) -> Vec<Field> {
+        node.children().filter_map(|c| match c.value() {
+            tokens::Tokens::ContentWithFormatting(v) => Some(Field::new(v)),
+            _ => None
+        }).collect()
+    }
+
+    //Split the fields of an expression between the arguments they were written for. The field filled by the l argument becomes the label.
+    fn resolve(parameters: &[DeclarationParameter], fields: Vec<Field>, n: Option<usize>, types: &TypeRegistry) -> (Option<Field>, Vec<Field>) {
+        let mut label: Option<Field> = None;
+        let mut contents: Vec<Field> = Vec::new();
+        let mut fields = fields.into_iter();
+        for i in parameters {
+            let count = match i {
+                DeclarationParameter::Type(s) if s == interpreter_structs::LABEL_TYPE && label.is_none() => {
+                    label = fields.next();
+                    continue
+                },
+                DeclarationParameter::Content(Count::Fixed(c)) => *c,
+                DeclarationParameter::Content(Count::Variable) => n.unwrap_or(0),
+                DeclarationParameter::Any => 1,
+                DeclarationParameter::Type(s) => match types.get(s).map(|t| arity(&t.header, types)) {
+                    Some(Ok((f, v))) => f + v * n.unwrap_or(0),
+                    _ => 0
+                }
+            };
+            contents.extend(fields.by_ref().take(count));
+        }
+        contents.extend(fields);    //Anything left over after a mismatch is kept rather than dropped.
+        (label, contents)
+    }
+
+    //Count the content fields taken up by a list of declaration arguments. Returns the fixed number of fields and the number of times "c..n" appears.
+    fn arity(parameters: &[DeclarationParameter], types: &TypeRegistry) -> Result<(usize, usize), String> {
+        let mut fixed: usize = 0;
+        let mut variable: usize = 0;
+        for i in parameters {
+            match i {
+                DeclarationParameter::Content(Count::Fixed(c)) => fixed += c,
+                DeclarationParameter::Content(Count::Variable) => variable += 1,
+                DeclarationParameter::Any => fixed += 1,
+                DeclarationParameter::Type(s) => {  //Types used as arguments are written inline, so they take up as many fields as their own header.
+                    match types.get(s) {
+                        Some(t) if t.elements.is_some() => return Err(format!("The collection \"{}\" can't be written inline as part of another type.", s)),
+                        Some(t) => {
+                            let (f, v) = arity(&t.header, types)?;
+                            fixed += f;
+                            variable += v;
+                        },
+                        None => return Err(format!("The type \"{}\" has not been declared.", s))
+                    }
+                }
+            }
+        }
+        Ok((fixed, variable))
+    }
+
+    //Check a number of fields against an arity, binding the variable count "n" the first time it is needed.
+    fn match_arity(fields: usize, (fixed, variable): (usize, usize), n: &mut Option<usize>) -> Result<(), String> {
+        if variable == 0 {
+            if fields != fixed {
+                return Err(format!("expected {} field(s), found {}.", fixed, fields))
+            }
+            return Ok(())
+        }
+        match n {
+            Some(n) => if fields != fixed + variable * *n {
+                return Err(format!("expected {} field(s) with n = {}, found {}.", fixed + variable * *n, n, fields))
+            },
+            None => {
+                if fields <= fixed || !(fields - fixed).is_multiple_of(variable) {
+                    return Err(format!("expected {} + {}n field(s) for some n of at least 1, found {}.", fixed, variable, fields))
+                }
+                *n = Some((fields - fixed) / variable);
+            }
+        }
+        Ok(())
+    }
+
+    //Write out declaration arguments the way they appear in the declaration, for error messages.
+    fn describe_parameters(parameters: &[DeclarationParameter]) -> String {
+        let words: Vec<String> = parameters.iter().map(|p| p.print()).collect();
+        format!("\"{}\"", words.join(" "))
+    }
+
+    //Write out the start of an expression (the type name and its first field), for error messages.
+    fn describe_expression(node: parser_structs::Node) -> String {
+        let name = match node.value() {
+            tokens::Tokens::TypeInstance(s) => s.clone(),
+            other => other.print()
+        };
+        let first = node.children().find_map(|c| match c.value() {
+            tokens::Tokens::ContentWithFormatting(v) => Some(v.iter().map(|t| match t {
+                tokens::Tokens::Content(s) | tokens::Tokens::CodeBlock(s) | tokens::Tokens::MathBlock(s) => s.trim().to_string(),
+                _ => String::new()
+            }).collect::<Vec<String>>().join(" ")),
+            _ => None
+        });
+        match first {
+            Some(f) => format!("{} {}", name, f),
+            None => name
         }
-        output
+    }
+}
+
+//Module containing the whole reading of a document, from its text to its objects, for tools that want every error in it at once.
+pub mod pipeline {
+    use crate::{lexer, parser, interpreter};
+    use crate::core::{errors::DiazoError, imports::ImportGraph, interpreter_structs::{DiazoObject, TypeRegistry}};
+
+    //Everything known about a document after reading it.
+    pub struct Analysis {
+        pub objects: Vec<DiazoObject>,  //Empty if the interpreter found any errors.
+        pub types: TypeRegistry,        //Every type the document knows about, from its own declarations and its imports.
+        pub graph: ImportGraph,
+        pub errors: Vec<DiazoError>     //Errors from every stage, in the order they were found.
+    }
+
+    //Read a document with every stage of the language, recovering from errors so that all of them are found.
+    pub fn analyse(input: String, config: &lexer::LexerConfig) -> Analysis {
+        let (tokens, mut errors, graph) = lexer::lexer_recovering_with(input, config);
+        let (ir, parser_errors) = parser::parser_recovering(tokens);
+        errors.extend(parser_errors);
+        let types = interpreter::registry(&ir).unwrap_or_default();     //Any error here is reported by the interpreter as well.
+        let objects = match interpreter::interpreter(ir) {
+            Ok(objects) => objects,
+            Err(e) => {
+                errors.extend(e);
+                Vec::new()
+            }
+        };
+        for e in &mut errors {
+            e.name_file(&graph, config.source.as_deref());
+        }
+        Analysis { objects, types, graph, errors }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::path::{Path, PathBuf};
+
+    //A directory of a test's own to write files in, removed when the test ends whether or not it passed.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let dir = std::env::temp_dir().join(format!("diazo_{}_{}", name, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl std::ops::Deref for TempDir {
+        type Target = Path;
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    //Run a document through the lexer, parser and interpreter, for tests of the objects that come out the other end.
+    fn interpret(input: &str) -> Vec<crate::core::interpreter_structs::DiazoObject> {
+        crate::interpreter::interpreter(crate::parser::parser(crate::lexer::lexer(input.to_string()).unwrap()).unwrap()).unwrap()
+    }
+
+    //The errors found in a document by every stage, as their kinds and the line and column they were found at.
+    fn errors(input: &str) -> Vec<(crate::core::errors::ErrorKind, usize, Option<usize>)> {
+        let analysis = crate::pipeline::analyse(input.to_string(), &crate::lexer::LexerConfig::default());
+        analysis.errors.iter().map(|e| (e.kind(), e.location().unwrap().line, e.location().unwrap().column)).collect()
+    }
+
     #[test]
     fn tree_builder() {
         use crate::core::tokens;
-        use std::rc::Rc;
-        use std::cell::RefCell;
-        use crate::core::parser_structs::TreeNode;
+        use crate::core::parser_structs::Tree;
 
         let v = vec![
             tokens::Tokens::Any,
@@ -1137,17 +1903,600 @@ mod tests {
             tokens::Tokens::CodeBlockOpen
         ];
 
-        let mut tree = Rc::new(RefCell::new(TreeNode::new(tokens::Tokens::TypeKeyword)));
-    
-        for i in v {
-            TreeNode::add(i, &tree)
+        let mut tree = Tree::new(tokens::Tokens::TypeKeyword, tokens::Span::default());
+        let root = tree.root();
+        let children: Vec<_> = v.into_iter().map(|i| tree.add(i, tokens::Span::default(), root)).collect();
+
+        println!("{}", tree.print(root));
+        let element = tree.add(tokens::Tokens::Element, tokens::Span::default(), children[1]);
+        println!("{}", tree.print(element));
+        assert_eq!(tree.parent(element), Some(children[1]));
+        assert_eq!((tree.parent(root), tree.previous_sibling(children[0]), tree.next_sibling(children[4])), (None, None, None));     //Nothing to find past either end, rather than a panic.
+        assert_eq!((tree.next_sibling(children[1]), tree.previous_sibling(children[1])), (Some(children[2]), Some(children[0])));
+        assert_eq!(children.iter().map(|&c| tree[c].index).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);    //Each node knows its place among its siblings.
+        assert_eq!(tree.node(element).parent().and_then(|p| p.next_sibling()).map(|n| n.value().clone()), Some(tokens::Tokens::Any));
+        assert_eq!((tree.len(), tree.iter().filter(|n| *n.value() == tokens::Tokens::Any).count()), (7, 2));
+
+        //The tree owns all of its nodes, so it can be printed with Debug and handed to another thread.
+        assert!(format!("{:?}", tree).contains("Element"));
+        let moved = std::thread::spawn(move || tree.len()).join().unwrap();
+        assert_eq!(moved, 7);
+    }
+
+    #[test]
+    fn tree_traversals() {
+        use crate::core::tokens::{Span, Tokens};
+        use crate::core::parser_structs::{Node, ReaderTuple, Tree, Visitor};
+
+        //a -> (b -> (d, e), c -> f)
+        let mut tree = Tree::new(Tokens::TypeName("a".to_string()), Span::default());
+        let name = |s: &str| Tokens::TypeName(s.to_string());
+        let b = tree.add(name("b"), Span::default(), tree.root());
+        let c = tree.add(name("c"), Span::default(), tree.root());
+        for (i, parent) in [("d", b), ("e", b), ("f", c)] {
+            tree.add(name(i), Span::default(), parent);
+        }
+        fn label(t: &Tokens) -> String {
+            match t {
+                Tokens::TypeName(s) => s.clone(),
+                other => other.print()
+            }
+        }
+        let names = |order: Vec<ReaderTuple>| order.iter().map(|t| label(&t.3)).collect::<Vec<_>>().join(" ");
+        assert_eq!(names(tree.preorder().collect()), "a b d e c f");
+        assert_eq!(names(tree.postorder().collect()), "d e b f c a");
+        assert_eq!(names(tree.breadth_first().collect()), "a b c d e f");
+        let last = tree.preorder().last().unwrap();
+        assert_eq!((last.1, last.2), (Some(c), 2));
+        assert_eq!(tree.node(b).preorder().map(|t| t.2).collect::<Vec<_>>(), vec![0, 1, 1]);     //Depths count from where the walk starts.
+        assert_eq!(Tree::new(Tokens::Any, Span::default()).preorder_read().len(), 1);                //A lone leaf is fine too.
+
+        //A visitor which skips everything under b.
+        struct Trace(Vec<String>);
+        impl Visitor for Trace {
+            fn enter(&mut self, node: Node, depth: usize) -> bool {
+                self.0.push(format!("+{}{}", label(node.value()), depth));
+                label(node.value()) != "b"
+            }
+            fn leave(&mut self, node: Node, _depth: usize) {
+                self.0.push(format!("-{}", label(node.value())));
+            }
+        }
+        let mut trace = Trace(Vec::new());
+        tree.walk(&mut trace);
+        assert_eq!(trace.0.join(" "), "+a0 +b1 -b +c1 +f2 -f -c -a");
+    }
+
+    #[test]
+    fn interpreter_matches_declarations() {
+        use crate::formatter;
+        use crate::core::errors::ErrorKind;
+
+        let declarations = "*type l => c\n*type def => l c\n*type lsdef => l e def\n*type tabl => l c..n e c c..n\n";
+        let valid = declarations.to_string() + "lsdef words\n\tcat -> a small animal\n\tdog -> a larger animal\n\ntabl things:: size,, color\n\tcat:: small,, orange\n";
+        let objects = interpret(&valid);
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0].elements[1].type_name.as_deref(), Some("def"));
+        assert_eq!(objects[0].elements[1].label.as_ref().unwrap().text(), "dog");
+        assert_eq!(objects[0].elements[1].contents[0].text(), "a larger animal");
+        assert_eq!(objects[1].label.as_ref().unwrap().text(), "things");
+        assert_eq!(objects[1].contents.len(), 2);
+        assert_eq!(objects[1].elements[0].contents.len(), 3);
+        assert_eq!(objects[0].iter().count(), 3);
+        assert_eq!(interpret(&formatter::format(&valid)), objects);     //Formatting never changes what a document means.
+
+        let mismatched = declarations.to_string() + "lsdef words\n\tcat -> a small animal -> too many\n\ntabl things:: size,, color\n\tcat:: small\n";
+        assert_eq!(errors(&mismatched), vec![(ErrorKind::TypeMismatch, 6, Some(1)), (ErrorKind::TypeMismatch, 9, Some(1))]);   //At the rows, not their collections.
+        assert_eq!(errors(&formatter::format(&mismatched)), errors(&mismatched));
+
+        //Tabs after the start of a line separate words like spaces do, in declarations and expressions alike.
+        let tabbed = interpret(&(declarations.replace("*type ", "*type\t") + "def\tcat ->\ta\tsmall animal\n"));
+        assert_eq!(tabbed[0].label.as_ref().unwrap().text(), "cat");
+        assert_eq!(tabbed[0].contents[0].text(), "a small animal");
+    }
+
+    #[test]
+    fn fields_keep_formatting_blocks() {
+        use crate::core::interpreter_structs::Fragment;
+
+        let input = "*type l => c\n*type def => l c\ndef pythagoras -> {{ a^2 + b^2 = c^2 }} for [[ right ]] triangles\n";
+        let objects = interpret(input);
+        assert_eq!(objects[0].contents[0].fragments, vec![
+            Fragment::Math("a^2 + b^2 = c^2".to_string()),
+            Fragment::Text(" for ".to_string()),
+            Fragment::Code("right".to_string()),
+            Fragment::Text(" triangles".to_string())
+        ]);
+
+        //Closing symbols attached to a word are taken off it.
+        let input = "*type l => c\n*type def => l c\ndef area -> {{pi r^2}} of a circle\n";
+        let objects = interpret(input);
+        assert_eq!(objects[0].contents[0].fragments[0], Fragment::Math("pi r^2".to_string()));
+    }
+
+    #[test]
+    fn errors_carry_their_location() {
+        use crate::{lexer, parser};
+        use crate::core::errors::{DiazoError, ErrorKind, Location};
+
+        let e = lexer::lexer("*type l => c\n\t*type h => c\n".to_string()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::TypeInScope);
+        assert_eq!(e.location(), Some(Location { file: None, line: 2, column: None, word: Some(2) }));
+        assert!(matches!(e, DiazoError::Lexer { ref token, .. } if token == "*type"));
+
+        let e = parser::parser(lexer::lexer("*type h => c\nh title\n\t\ttoo deep\n".to_string()).unwrap()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::OverIndented);
+        assert_eq!((e.location().unwrap().line, e.location().unwrap().column), (3, Some(3)));
+
+        //The interpreter points at the part of the expression that failed by its span, with the file named once the import graph is known.
+        let config = crate::lexer::LexerConfig { prelude: true, ..crate::lexer::LexerConfig::file("notes.dz") };
+        let analysis = crate::pipeline::analyse("h title\nh title\n\textra\n*type l => c\n".to_string(), &config);
+        let locations: Vec<String> = analysis.errors.iter().map(|e| e.location().unwrap().to_string()).collect();
+        assert_eq!(locations, vec!["notes.dz, line 4, word 2", "notes.dz, line 3, column 1"]);   //The lexer's error comes first, and counts words instead.
+        let analysis = crate::pipeline::analyse("tabl cells:: a,, b\n\tone:: 1,, 2\n\ttwo:: 3\n\tthree:: 4,, 5\n".to_string(), &config);
+        let e = &analysis.errors[0];    //A row with a missing field, found at its own line rather than the table's.
+        assert_eq!((analysis.errors.len(), e.kind()), (1, ErrorKind::TypeMismatch));
+        assert_eq!((e.location().unwrap().line, e.location().unwrap().column), (3, Some(1)));
+        assert!(matches!(e, DiazoError::Interpreter { expression, .. } if expression == "tabl cells"));
+
+        let e = lexer::lexer("*use missing.dz\n".to_string()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Import);
+    }
+
+    #[test]
+    fn recovery_reports_every_error() {
+        use crate::{lexer, parser, interpreter};
+        use crate::core::errors::ErrorKind;
+
+        let input = "*type l => c\n\t*type h => c\n*type def => l c\n*type l => c\ndef a -> b\n\t\ttoo deep\ndef c -> d\n".to_string();
+        let (tokens, lexer_errors) = lexer::lexer_recovering(input.clone());
+        assert_eq!(lexer_errors.iter().map(|e| (e.kind(), e.location().unwrap().line)).collect::<Vec<_>>(),
+            vec![(ErrorKind::TypeInScope, 2), (ErrorKind::DuplicateType, 4)]);
+        let (ir, parser_errors) = parser::parser_recovering(tokens);
+        assert_eq!(parser_errors.iter().map(|e| (e.kind(), e.location().unwrap().line)).collect::<Vec<_>>(),
+            vec![(ErrorKind::OverIndented, 6)]);
+        assert_eq!(interpreter::interpreter(ir).unwrap().len(), 2);
+
+        //The pipeline gathers the errors of every stage in the order they were found.
+        let analysis = crate::pipeline::analyse(input.clone(), &lexer::LexerConfig::default());
+        assert_eq!(analysis.errors.iter().map(|e| e.kind()).collect::<Vec<_>>(), vec![ErrorKind::TypeInScope, ErrorKind::DuplicateType, ErrorKind::OverIndented]);
+        assert_eq!((analysis.objects.len(), analysis.types.len()), (2, 2));
+
+        //Arguments cut short while typing are errors rather than panics.
+        let (_, lexer_errors) = lexer::lexer_recovering("*type x => e.\n*type y => c.\n*type z => c\u{e9}\n".to_string());
+        assert_eq!(lexer_errors.iter().map(|e| e.kind()).collect::<Vec<_>>(),
+            vec![ErrorKind::InvalidElementArgument, ErrorKind::InvalidContentArgument, ErrorKind::InvalidContentArgument]);
+    }
+
+    #[test]
+    fn registry_lists_imported_types() {
+        use crate::{lexer, parser, interpreter};
+        use crate::core::interpreter_structs::{Count, DeclarationParameter};
+
+        let types = interpreter::registry(&parser::parser(lexer::lexer("*use prelude.dz\n".to_string()).unwrap()).unwrap()).unwrap();
+        assert_eq!(types.len(), 20);
+        assert_eq!(types.names()[..3], ["l", "h", "y"]);
+        let tabl = types.get("tabl").unwrap();
+        assert_eq!(tabl.signature(), "l c..n e c c..n");
+        assert_eq!(tabl.header, vec![DeclarationParameter::Type("l".to_string()), DeclarationParameter::Content(Count::Variable)]);
+        assert_eq!(tabl.elements.as_ref().unwrap().count, Count::Variable);
+    }
+
+    #[test]
+    fn imports_are_transitive_and_cycle_safe() {
+        use crate::{lexer, parser, interpreter};
+        use crate::core::errors::ErrorKind;
+        use std::fs;
+
+        let dir = TempDir::new("imports");
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("base.dz"), "*type l => c\n*type h => l").unwrap();
+        fs::write(path("lib.dz"), format!("*use {}\n*type pair => l c\n", path("base.dz"))).unwrap();
+        fs::write(path("x.dz"), format!("*use {}\n", path("y.dz"))).unwrap();
+        fs::write(path("y.dz"), format!("*use {}\n", path("x.dz"))).unwrap();
+
+        //base.dz is imported twice but only declared once, and lib.dz can use its types either way.
+        let (tokens, _, graph) = lexer::lexer_with(format!("*use {}\n*use {}\npair a -> b\n", path("base.dz"), path("lib.dz")), &lexer::LexerConfig::default()).unwrap();
+        assert_eq!(graph.files, vec![path("base.dz"), path("lib.dz")]);
+        assert_eq!(graph.edges, vec![(None, 0), (None, 1), (Some(1), 0)]);
+        assert_eq!(graph.imports_of(Some(&path("lib.dz"))), vec![path("base.dz")]);
+        let types = interpreter::registry(&parser::parser(tokens).unwrap()).unwrap();
+        assert_eq!(types.names(), ["l", "h", "pair"]);
+        fs::write(path("private.dz"), "*type p\u{E000} => c\n").unwrap();     //Text from an import is only unescaped once, by the lexer that read it.
+        let tokens = lexer::lexer(format!("*use {}\n", path("private.dz"))).unwrap();
+        assert!(tokens.iter().any(|t| t.value == crate::core::tokens::Tokens::TypeName("p\u{E000}".to_string())));
+
+        let e = lexer::lexer(format!("*use {}\n", path("x.dz"))).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::ImportCycle);
+        assert_eq!(e.location().unwrap().file, Some(path("y.dz")));
+        assert!(e.message().ends_with(&format!("{} -> {} -> {}", path("x.dz"), path("y.dz"), path("x.dz"))));
+    }
+
+    #[test]
+    fn imports_resolve_next_to_the_importer() {
+        use crate::lexer;
+        use crate::core::errors::ErrorKind;
+        use std::fs;
+
+        let dir = TempDir::new("resolve");
+        fs::create_dir_all(dir.join("notes")).unwrap();
+        fs::create_dir_all(dir.join("library")).unwrap();
+        fs::write(dir.join("library/base.dz"), "*type l => c\n").unwrap();
+        fs::write(dir.join("library/lib.dz"), "*use base.dz\n*type def => l c\n").unwrap();   //Found next to lib.dz.
+        fs::write(dir.join("notes/note.dz"), "*use lib.dz\ndef a -> b\n").unwrap();           //Found in the search path.
+
+        let note = dir.join("notes/note.dz").to_str().unwrap().to_string();
+        let input = fs::read_to_string(&note).unwrap();
+        let config = lexer::LexerConfig { search_path: vec![dir.join("library")], ..lexer::LexerConfig::file(&note) };
+        let (_, _, graph) = lexer::lexer_with(input.clone(), &config).unwrap();
+        let library = fs::canonicalize(dir.join("library")).unwrap();
+        assert_eq!(graph.files, vec![library.join("lib.dz").to_str().unwrap(), library.join("base.dz").to_str().unwrap()]);
+
+        let e = lexer::lexer_with(input, &lexer::LexerConfig { search_path: Vec::new(), ..config }).unwrap_err();
+        assert_eq!((e.kind(), e.location()), (ErrorKind::Import, None));
+    }
+
+    #[test]
+    fn std_is_built_in() {
+        use crate::{lexer, parser, interpreter};
+        use crate::core::tokens::Tokens;
+        use std::fs;
+
+        let types = interpreter::registry(&parser::parser(lexer::lexer("*use std\n".to_string()).unwrap()).unwrap()).unwrap();
+        assert_eq!(types.len(), 20);
+
+        let dir = TempDir::new("std");
+        let file = dir.join("std.dz");
+        fs::write(&file, "*use std\ndef cell -> the basic unit of life\n").unwrap();
+        let config = lexer::LexerConfig { prelude: true, ..lexer::LexerConfig::file(file.to_str().unwrap()) };
+        let (tokens, _, graph) = lexer::lexer_with(fs::read_to_string(&file).unwrap(), &config).unwrap();   //The explicit *use std is the same import as the implicit one.
+        assert_eq!((graph.files.clone(), graph.edges.clone()), (vec!["std".to_string()], vec![(None, 0), (None, 0)]));
+        assert_eq!(interpreter::interpreter(parser::parser(tokens).unwrap()).unwrap().len(), 1);
+
+        let input = "def cell -> the basic unit of life\n".to_string();
+        assert!(lexer::lexer_with(input.clone(), &config).is_ok());
+        let (tokens, _, graph) = lexer::lexer_with(input, &lexer::LexerConfig { prelude: false, ..config }).unwrap();
+        assert!(graph.files.is_empty() && !tokens.iter().any(|t| t.value == Tokens::TypeInstance("def".to_string())));  //Without the prelude, def is just text.
+
+        //Older files import their own copy of the standard library, which adds nothing to std, even if the copy is out of date.
+        fs::write(dir.join("prelude.dz"), "// Definitions of diazo's standard types\n*type l => c \t\t\t// A label\n*type def => l c \t\t// Definition as term, definition\n").unwrap();
+        let config = lexer::LexerConfig { prelude: true, ..lexer::LexerConfig::file(dir.join("notes.dz").to_str().unwrap()) };
+        let analysis = crate::pipeline::analyse("*use prelude.dz\ndef cell -> the basic unit of life\n".to_string(), &config);
+        assert!(analysis.errors.is_empty());
+        assert_eq!((analysis.objects.len(), analysis.types.len()), (1, 20));
+        let e = lexer::lexer_with("*use std\n*type l => c\n".to_string(), &config).unwrap_err();  //Clashing with an import says which file it was.
+        assert!(e.message().contains("declared in std"));
+    }
+
+    #[test]
+    fn lexer_config_applies_to_imports() {
+        use crate::lexer::{self, LexerConfig};
+        use crate::core::errors::ErrorKind;
+        use std::fs;
+
+        let dir = TempDir::new("config");
+        let file = dir.join("lib.dz");
+        fs::write(&file, "*type l => c\n\t*type h => c\n").unwrap();
+        let file = file.to_str().unwrap().to_string();
+
+        //Errors in imported files are located in them, since they are lexed with their own name as the source.
+        let e = lexer::lexer(format!("*use {}\n", file)).unwrap_err();
+        assert_eq!((e.kind(), e.location().unwrap().file.clone()), (ErrorKind::TypeInScope, Some(fs::canonicalize(&file).unwrap().to_str().unwrap().to_string())));
+
+        let e = lexer::lexer_with(format!("*use {}\n", file), &LexerConfig { allow_imports: false, ..LexerConfig::default() }).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::ImportNotAllowed);
+
+        let config = LexerConfig { types: vec!["def".to_string()], ..LexerConfig::default() };
+        let (tokens, _, _) = lexer::lexer_with("def a -> b\n".to_string(), &config).unwrap();
+        assert_eq!(tokens[0].value, crate::core::tokens::Tokens::TypeInstance("def".to_string()));
+    }
+
+    #[test]
+    fn tokens_carry_spans() {
+        use crate::{lexer, parser};
+        use crate::core::tokens::{Span, Tokens};
+
+        let config = lexer::LexerConfig { prelude: true, recover: true, ..lexer::LexerConfig::default() };
+        let (tokens, _, _) = lexer::lexer_with("h title\nl  first word\n\t\tl too deep\n".to_string(), &config).unwrap();
+        let own: Vec<_> = tokens.iter().filter(|t| t.span.file.is_none() && t.span.line > 0).collect();
+        assert_eq!((own[0].value.clone(), own[0].span), (Tokens::TypeInstance("h".to_string()), Span { file: None, offset: 0, line: 1, column: 1 }));
+        assert_eq!((own[4].value.clone(), own[4].span), (Tokens::Content("first word ".to_string()), Span { file: None, offset: 11, line: 2, column: 4 }));
+        assert!(tokens.iter().any(|t| t.span.file == Some(0)));     //The prelude's declarations point back into it.
+
+        //The lines the prelude adds are no longer counted, so errors are placed in the file itself.
+        let (_, errors) = parser::parser_recovering(tokens);
+        assert_eq!(errors[0].location().unwrap().line, 3);
+    }
+
+    #[test]
+    fn space_indentation() {
+        use crate::{lexer, parser, interpreter, formatter};
+        use crate::core::errors::ErrorKind;
+
+        //Spaces are only levels of indentation once they've been allowed, by the pragma or the lexer's settings.
+        let declarations = "*type l => c\n*type seq => l e any\n*type exp => l e any\n";
+        let body = "exp outer\n  first\n  seq inner\n    deep\n";
+        let tabbed = interpret(&(declarations.to_string() + &body.replace("  ", "\t")));
+        assert_eq!(tabbed[0].elements[1].elements.len(), 1);
+        assert_eq!(interpret(&format!("// spaces\n\n*indent 2\n{}{}", declarations, body)), tabbed);
+        let config = lexer::LexerConfig { indent_spaces: Some(2), ..lexer::LexerConfig::default() };
+        let (tokens, _, _) = lexer::lexer_with(declarations.to_string() + body, &config).unwrap();
+        assert_eq!(interpreter::interpreter(parser::parser(tokens).unwrap()).unwrap(), tabbed);
+
+        let kind = |input: &str| lexer::lexer(input.to_string()).unwrap_err().kind();
+        assert_eq!(kind("*type l => c\nl a\n \tb\n"), ErrorKind::MixedIndentation);
+        assert_eq!(kind("*indent 4\n*type l => c\nl a\n      b\n"), ErrorKind::UnevenIndentation);
+        assert_eq!(kind("*type l => c\n*indent 4\n"), ErrorKind::IndentPragma);
+        assert_eq!(kind("*indent four\n"), ErrorKind::IndentPragma);
+        assert!(lexer::lexer("*type l => c\nl a\n\t \n".to_string()).is_ok());    //Blank lines can be indented any which way.
+
+        //The formatter keeps to the indentation the pragma asks for.
+        assert_eq!(formatter::format("*indent 2\nexp a\n\tb\n    c\n"), "*indent 2\nexp a\n  b\n    c\n");
+        let nested = "exp outer\n  first\n  seq inner\n    deep\n";   //As with --indent 2.
+        assert_eq!(formatter::format_with(nested, Some(2)), nested);
+        assert_eq!(formatter::format_with("exp outer\n\tfirst\n\tseq inner\n\t\tdeep\n", Some(2)), nested);
+        assert_eq!(formatter::format("exp a\n\tb\n    c\n"), "exp a\n\tb\nc\n");    //Without space indentation, spaces aren't a level.
+    }
+
+    #[test]
+    fn escaped_symbols() {
+        use crate::{lexer, cst, formatter};
+        use crate::core::{interpreter_structs::Fragment, tokens::Tokens};
+        use crate::core::syntax::SyntaxKind;
+
+        let input = "*type l   => c\n*type def => l c\ndef std\\::vector -> see [[ std\\::vector<int> \\]] ]] and {{ \\{x\\} }}\n\\// a \\[[ b \\-> c\n";
+        let objects = interpret(input);
+        assert_eq!(objects[0].label.as_ref().unwrap().fragments, vec![Fragment::Text("std::vector".to_string())]);
+        assert_eq!(objects[0].contents[0].fragments, vec![Fragment::Text("see ".to_string()), Fragment::Code("std::vector<int> ]]".to_string()),
+            Fragment::Text(" and ".to_string()), Fragment::Math("\\{x\\}".to_string())]);   //Backslashes before anything else are left alone.
+        assert_eq!(objects[1].contents[0].fragments, vec![Fragment::Text("// a [[ b -> c".to_string())]);
+
+        //Every token with text in it loses its escapes, and a doubled backslash before a symbol is a backslash that leaves the symbol working.
+        let doubled = "*type l => c\n*type a\\->b => l\na\\->b C:\\\\:: x \\\\\\-> \\\\y\n";
+        let tokens: Vec<Tokens> = lexer::lexer(doubled.to_string()).unwrap().into_iter().map(|t| t.value).collect();
+        assert!(tokens.contains(&Tokens::TypeName("a->b".to_string())) && tokens.contains(&Tokens::TypeInstance("a->b".to_string())));
+        assert!(tokens.ends_with(&[Tokens::Content("C:\\ ".to_string()), Tokens::Separator("::".to_string()), Tokens::Content("x \\-> \\\\y ".to_string())]));
+        let private = "\u{E000}\u{E001}\u{E00B}\u{E0FF}";    //Characters of Unicode's private use area, some of which the lexer hides escaped symbols behind.
+        let tokens = lexer::lexer(format!("a{} \\:: b{}\n", private, private)).unwrap();
+        assert_eq!(tokens[0].value, Tokens::Content(format!("a{} :: b{} ", private, private)));
+
+        //The concrete syntax tree leaves them in the words they're written in, so the formatter doesn't touch them either.
+        let tree = cst::cst(input);
+        assert!(tree.children[2].leaves().iter().any(|l| l.kind == SyntaxKind::Word && l.text == "std\\::vector"));
+        assert_eq!(tree.children[3].kind, SyntaxKind::ExpressionLine);
+        assert!(formatter::check(input));
+        assert!(cst::cst(doubled).children[2].leaves().iter().any(|l| l.kind == SyntaxKind::Separator && l.text == "::"));
+    }
+
+    #[test]
+    fn fenced_code_blocks() {
+        use crate::{lexer, cst, formatter};
+        use crate::core::{errors::ErrorKind, interpreter_structs::Fragment, syntax::SyntaxKind, tokens::Tokens};
+        use crate::exporters::{html, markdown};
+
+        let input = "*type l   => c\n*type def => l c\ndef run -> see ```rust\nfn main() {\n    let  x = [[1]];  // not a comment\n\n}\n```\ndef empty -> ```\n\t```\n";
+        let tokens: Vec<Tokens> = lexer::lexer(input.to_string()).unwrap().into_iter().map(|t| t.value).collect();
+        assert!(tokens.contains(&Tokens::CodeFence("rust".to_string())));
+        assert!(tokens.contains(&Tokens::CodeBlock("fn main() {\n    let  x = [[1]];  // not a comment\n\n}".to_string())));
+        let objects = interpret(input);
+        let code = "fn main() {\n    let  x = [[1]];  // not a comment\n\n}".to_string();
+        assert_eq!(objects[0].contents[0].fragments, vec![Fragment::Text("see ".to_string()), Fragment::Fenced { language: Some("rust".to_string()), code }]);
+        assert_eq!(objects[1].contents[0].fragments, vec![Fragment::Fenced { language: None, code: String::new() }]);
+        assert!(html::html(&objects).contains("<pre><code class=\"language-rust\">fn main() {\n    let  x = [[1]];  // not a comment\n\n}</code></pre>"));
+        assert!(markdown::markdown(&objects).contains("\n```rust\nfn main() {"));
+
+        //Lines inside the fence are kept whole by the concrete syntax tree, and left alone by the formatter.
+        let tree = cst::cst(input);
+        assert!(tree.children[2].leaves().iter().any(|l| l.kind == SyntaxKind::Fence && l.text == "```rust"));
+        assert_eq!(tree.children[4].leaves()[0].kind, SyntaxKind::Code);
+        assert_eq!(tree.children[7].kind, SyntaxKind::CodeLine);
+        assert!(formatter::check(input));
+        assert_eq!(tree.source(), input);
+
+        let unclosed = "*type l   => c\nl ```\ncode\n".to_string();
+        assert_eq!(lexer::lexer(unclosed.clone()).unwrap_err().kind(), ErrorKind::UnclosedCodeFence);
+        assert_eq!(errors(&unclosed), vec![(ErrorKind::UnclosedCodeFence, 2, None)]);      //Found at the opening fence.
+        let (tokens, errors) = lexer::lexer_recovering(unclosed);
+        assert!(tokens.iter().any(|t| t.value == Tokens::CodeBlock("code".to_string())));
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn html_export() {
+        use crate::exporters::html;
+
+        let input = "*use std\nh cells & such\nlsdef words\n\tatp -> energy {{ E = mc^2 }}\nseq steps\n\tfirst\n\tsecond\ntabl walls:: material,, color\n\tplants:: [[ cellulose ]],, green\n";
+        let objects = interpret(input);
+        let page = html::html(&objects);
+        assert!(page.contains("<title>cells &amp; such</title>"));
+        assert!(page.contains("<h1>cells &amp; such</h1>"));
+        assert!(page.contains("<h2>words</h2>\n<dl class=\"lsdef\">\n<dt>atp</dt>\n<dd>energy <span class=\"math\">\\(E = mc^2\\)</span></dd>"));
+        assert!(page.contains("<ol class=\"seq\">\n<li>first</li>\n<li>second</li>\n</ol>"));
+        assert!(page.contains("<caption>walls</caption>\n<thead>\n<tr><th></th><th scope=\"col\">material</th><th scope=\"col\">color</th></tr>"));
+        assert!(page.contains("<tr><th scope=\"row\">plants</th><td><code>cellulose</code></td><td>green</td></tr>"));
+    }
+
+    #[test]
+    fn markdown_export() {
+        use crate::exporters::markdown;
+
+        let input = "*use std\nh cells\ndef atp -> energy {{ E = mc^2 }}\nexp roles\n\tprotect [[ DNA ]]\n\tseq steps\n\t\tfirst\n\t\tsecond\ntabl walls:: material\n\tplants:: cellulose | lignin\n\tfungi:: [[ a|b ]]\n";
+        let objects = interpret(input);
+        assert_eq!(markdown::markdown(&objects), "# cells\n\n**atp**: energy $E = mc^2$\n\n## roles\n\n- protect `DNA`\n- steps\n  1. first\n  1. second\n\n## walls\n\n|  | material |\n| --- | --- |\n| plants | cellulose \\| lignin |\n| fungi | `a\\|b` |\n");
+
+        let input = "*use std\nexp empty\nseq steps\ntabl none:: column\nh end\n";
+        let objects = interpret(input);
+        assert_eq!(markdown::markdown(&objects), "## empty\n\n## steps\n\n## none\n\n# end\n");
+    }
+
+    #[test]
+    fn latex_export() {
+        use crate::exporters::latex;
+
+        let input = "*use std\nh cells\nlsdef words\n\tatp -> energy {{ E = mc^2 }}\n\tx[1] -> first\nexp roles\n\tprotect [[ dna_pol ]] & 50% of it\n\tseq steps\n\t\tfirst\ntabl walls:: material,, color\n\tplants:: cellulose,, green\n";
+        let objects = interpret(input);
+        let document = latex::latex(&objects);
+        assert!(document.starts_with("\\documentclass{article}") && document.ends_with("\\end{document}\n"));
+        assert!(document.contains("\\section{cells}"));
+        assert!(document.contains("\\subsection*{words}\n\n\\begin{description}\n\\item[{atp}] energy $E = mc^2$\n\\item[{x[1]}] first\n\\end{description}"));
+        assert!(document.contains("\\begin{itemize}\n\\item protect \\texttt{dna\\_pol} \\& 50\\% of it\n\\item steps\n\\begin{enumerate}\n\\item first\n\\end{enumerate}"));
+        assert!(document.contains("\\begin{tabular}{l|ll}\n & material & color \\\\\n\\hline\nplants & cellulose & green \\\\\n\\end{tabular}"));
+
+        //Verbatim can't go in a table cell or a term, so a fenced block in one is written on one line in a typewriter font.
+        let input = "*use std\ntabl code:: source\n\trust:: ```\nlet a = b & c;\n```\n";
+        let document = latex::latex(&interpret(input));
+        assert!(document.contains("rust & \\texttt{let a = b \\& c;} \\\\\n"));
+        assert!(!document.contains("verbatim"));
+    }
+
+    #[test]
+    fn json_export() {
+        use crate::{lexer, parser, interpreter};
+        use crate::exporters::json;
+
+        let input = "*type l => c\n*type def => l c\ndef \"quoted\" -> {{ x\\y }}\n".to_string();
+        let ir = parser::parser(lexer::lexer(input).unwrap()).unwrap();
+        let ast = json::ast(&ir);
+        assert!(ast.starts_with("{\"version\":1,\"statements\":[{\"kind\":\"declaration\",\"tree\":{\"token\":{\"type\":\"TypeKeyword\"},\"span\":{\"file\":null,\"offset\":0,\"line\":1,\"column\":1},\"children\":["));
+        assert!(ast.contains("{\"kind\":\"expression\",\"tree\":{\"token\":{\"type\":\"TypeInstance\",\"value\":\"def\"},\"span\":{\"file\":null,\"offset\":30,\"line\":3,\"column\":1},\"children\":["));
+        let objects = interpreter::interpreter(ir).unwrap();
+        assert_eq!(json::objects(&objects), "{\"version\":1,\"objects\":[{\"type\":\"def\",\"label\":[{\"kind\":\"text\",\"text\":\"\\\"quoted\\\"\"}],\"contents\":[[{\"kind\":\"math\",\"text\":\"x\\\\y\"}]],\"elements\":[]}]}\n");
+    }
+
+    #[test]
+    fn anki_export() {
+        use crate::{lexer, parser, interpreter};
+        use crate::exporters::anki;
+
+        let input = "*use std\n*type fact => l c @card\ndef early -> before any heading\nh cells\nlsdef words\n\tatp -> energy {{ E = mc^2 }}\nexp facts\n\tfact water -> wet\n\tplain text\nh genes\nstat 3 billion :: base pairs\n".to_string();
+        let ir = parser::parser(lexer::lexer(input).unwrap()).unwrap();
+        let types = interpreter::registry(&ir).unwrap();
+        assert!(types.get("def").unwrap().annotated(anki::CARD));
+        let cards = anki::anki(&interpreter::interpreter(ir).unwrap(), &types);
+        assert_eq!(cards.lines().filter(|l| !l.starts_with('#')).collect::<Vec<_>>(), vec![
+            "early\tbefore any heading\tdiazo\tdef",
+            "atp\tenergy <span class=\"math\">\\(E = mc^2\\)</span>\tcells\tlsdef def",
+            "water\twet\tcells\texp fact",
+            "3 billion\tbase pairs\tgenes\tstat"
+        ]);
+    }
+
+    #[test]
+    fn csv_export() {
+        use crate::exporters::csv;
+
+        let input = "*use std\ntabl cell walls:: material,, notes\n\teubacteria:: peptidoglycan,, \"thick\", mostly\n\tplants:: cellulose,, none\nexp nested\n\ttabl cell walls:: material\n\t\tfungi:: chitin\n";
+        let objects = interpret(input);
+        let files = csv::tables(&objects, ',');
+        assert_eq!(files.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(), vec!["cell_walls.csv", "cell_walls_2.csv"]);
+        assert_eq!(files[0].contents, ",material,notes\r\neubacteria,peptidoglycan,\"\"\"thick\"\", mostly\"\r\nplants,cellulose,none\r\n");
+        assert_eq!(csv::tables(&objects, '\t')[1].contents, "\tmaterial\r\nfungi\tchitin\r\n");
+    }
+
+    #[test]
+    fn cst_is_lossless() {
+        use crate::cst;
+        use crate::core::syntax::SyntaxKind;
+
+        let input = "// notes\r\n*type def => l c @card\t// a term\n\n\tdef word::[[x->y]] -> meaning // not a comment \n/* a\nblock */ def a :: b\n  \n*use std";
+        let tree = cst::cst(input);
+        assert_eq!(tree.source(), input);
+        for file in [crate::filehandling::PRELUDE, include_str!("../prokaryotes.dz")] {
+            assert_eq!(cst::cst(file).source(), file);
+        }
+
+        let kinds: Vec<SyntaxKind> = tree.children.iter().map(|l| l.kind).collect();
+        assert_eq!(kinds, vec![SyntaxKind::CommentLine, SyntaxKind::DeclarationLine, SyntaxKind::BlankLine, SyntaxKind::ExpressionLine,
+            SyntaxKind::CommentLine, SyntaxKind::ExpressionLine, SyntaxKind::BlankLine, SyntaxKind::UseLine]);
+        let leaves = |line: usize| tree.children[line].leaves().into_iter().filter(|l| l.kind != SyntaxKind::Whitespace).map(|l| (l.kind, l.text.as_str())).collect::<Vec<_>>();
+        assert_eq!(leaves(1), vec![(SyntaxKind::TypeKeyword, "*type"), (SyntaxKind::TypeName, "def"), (SyntaxKind::Assignment, "=>"), (SyntaxKind::Parameter, "l"),
+            (SyntaxKind::Parameter, "c"), (SyntaxKind::Annotation, "@card"), (SyntaxKind::Comment, "// a term"), (SyntaxKind::Newline, "\n")]);
+        assert_eq!(leaves(3), vec![(SyntaxKind::Indent, "\t"), (SyntaxKind::Word, "def"), (SyntaxKind::Word, "word::"),    //The lexer reads a separator inside a word as ending the field after it, so it stays put.
+            (SyntaxKind::CodeOpen, "[["), (SyntaxKind::Word, "x->y"), (SyntaxKind::CodeClose, "]]"),
+            (SyntaxKind::Separator, "->"), (SyntaxKind::Word, "meaning"), (SyntaxKind::Word, "//"), (SyntaxKind::Word, "not"), (SyntaxKind::Word, "a"),
+            (SyntaxKind::Word, "comment"), (SyntaxKind::Newline, "\n")]);
+        assert_eq!(leaves(5)[0], (SyntaxKind::Comment, "block */"));
+    }
+
+    #[test]
+    fn formatter_is_canonical() {
+        use crate::{formatter, lexer};
+        use crate::core::tokens::Tokens;
+
+        let input = "*type ab => c   // a\n*type abcdef => l e ab\t// b\n*type x => c\n\n// notes   \n    def a   -> b::  c::  e::f [[p::q]]  \n  \n\n";
+        let expected = "*type ab     => c\t\t// a\n*type abcdef => l e ab\t// b\n*type x      => c\n\n// notes\ndef a -> b :: c :: e::f [[p::q]]\n";  //Spaces only indent a line when space indentation is on.
+        assert_eq!(formatter::format(input), expected);
+        assert!(formatter::check(expected));
+        assert!(formatter::check(crate::filehandling::PRELUDE));
+        assert!(formatter::check(include_str!("../prokaryotes.dz")));
+
+        //Formatting changes only whitespace, so the lexer reads the same tokens afterwards, and a second pass changes nothing.
+        let tokens = |s: &str| {
+            let (tokens, errors) = lexer::lexer_recovering(s.to_string());
+            let mut values: Vec<Tokens> = tokens.into_iter().map(|t| t.value).collect();
+            while values.last() == Some(&Tokens::Linebreak) {   //Trailing blank lines are dropped.
+                values.pop();
+            }
+            (values, errors.iter().map(|e| e.kind()).collect::<Vec<_>>())
+        };
+        for file in [input, crate::filehandling::PRELUDE, include_str!("../prokaryotes.dz"), "def a::b  ->c\n", "*type:: /* d\ne\n", "\n *use,,->a::\te.\t\\\\  "] {
+            let formatted = formatter::format(file);
+            assert_eq!(tokens(file), tokens(&formatted), "{:?}", file);
+            assert_eq!(formatter::format(&formatted), formatted, "{:?}", file);
         }
+    }
 
-        println!("{}", TreeNode::print(&*tree.borrow()));
-        tree = TreeNode::add_and_set(tokens::Tokens::Element, &tree);
-        println!("{}", TreeNode::print(&*tree.borrow()));
-        tree = TreeNode::back_to_parent(&tree);
-        println!("{}", TreeNode::print(&*tree.borrow()));
+    #[test]
+    fn lsp_answers_requests() {
+        use crate::lsp::{messages, positions, server::Server, value::{self, Json}};
+        use std::fs;
+
+        let message = value::parse("{\"id\": 1, \"params\": {\"text\": \"a\\n\\u00e9\\ud83d\\ude00\", \"list\": [1.5, -2, true, null, {}]}}").unwrap();
+        assert_eq!(message.get("params").get("text").as_str(), Some("a\né😀"));
+        assert_eq!(value::parse(&message.to_string()).unwrap(), message);
+        assert!(value::parse("{\"a\": }").is_err());
+        let mut framed: Vec<u8> = Vec::new();
+        messages::write(&mut framed, "{}").unwrap();
+        assert_eq!(messages::read(&mut framed.as_slice()).unwrap(), Some("{}".to_string()));
+
+        let dir = TempDir::new("lsp");
+        fs::write(dir.join("lib.dz"), "*use std\n*type pair => l c @card\n").unwrap();
+        let lib = positions::uri(&fs::canonicalize(dir.join("lib.dz")).unwrap().to_string_lossy());
+        let uri = positions::uri(&dir.join("doc.dz").to_string_lossy());
+        let request = |method: &str, params: Json| Json::object(vec![("jsonrpc", Json::string("2.0")), ("id", Json::Number(1.0)), ("method", Json::string(method)), ("params", params)]);
+        let at = |line: f64, character: f64| Json::object(vec![("textDocument", Json::object(vec![("uri", Json::string(&uri))])),
+            ("position", Json::object(vec![("line", Json::Number(line)), ("character", Json::Number(character))]))]);
+        let mut server = Server::new(Vec::new(), true);
+
+        let opened = server.handle(&request("textDocument/didOpen", Json::object(vec![("textDocument", Json::object(vec![("uri", Json::string(&uri)),
+            ("text", Json::string("*use lib.dz\npair a :: b\n*type def => c\n"))]))])));
+        let diagnostics = opened[0].get("params").get("diagnostics").as_array();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].get("code").as_str(), Some("duplicate-type"));
+        assert_eq!(diagnostics[0].get("range").get("start").get("line").as_usize(), Some(2));
+        assert_eq!(diagnostics[0].get("range").get("start").get("character").as_usize(), Some(6));
+
+        let completion = &server.handle(&request("textDocument/completion", at(1.0, 0.0)))[0];
+        assert!(completion.get("result").as_array().iter().any(|i| i.get("label").as_str() == Some("pair") && i.get("detail").as_str() == Some("pair => l c @card")));
+        let hover = &server.handle(&request("textDocument/hover", at(1.0, 2.0)))[0];
+        assert!(hover.get("result").get("contents").get("value").as_str().unwrap().contains("pair => l c @card"));
+        let definition = &server.handle(&request("textDocument/definition", at(1.0, 2.0)))[0];
+        assert_eq!(definition.get("result").get("uri").as_str(), Some(lib.as_str()));
+        assert_eq!(definition.get("result").get("range").get("start").get("line").as_usize(), Some(1));
+        assert_eq!(server.handle(&request("textDocument/hover", at(1.0, 7.0)))[0].get("result"), &Json::Null);
+        let repeated = server.handle(&request("textDocument/didChange", Json::object(vec![("textDocument", Json::object(vec![("uri", Json::string(&uri))])),
+            ("contentChanges", Json::Array(vec![Json::object(vec![("text", Json::string("*use lib.dz\nh title\nh title\n\textra\n"))])]))])));
+        let diagnostics = repeated[0].get("params").get("diagnostics").as_array();     //The element under the second of two identical expressions, found by its span.
+        assert_eq!((diagnostics.len(), diagnostics[0].get("code").as_str()), (1, Some("type-mismatch")));
+        assert_eq!(diagnostics[0].get("range").get("start").get("line").as_usize(), Some(3));
+        assert_eq!(diagnostics[0].get("range").get("end").get("character").as_usize(), Some(6));
+        server.handle(&request("textDocument/didChange", Json::object(vec![("textDocument", Json::object(vec![("uri", Json::string(&uri))])),
+            ("contentChanges", Json::Array(vec![Json::object(vec![("text", Json::string("*use lib.dz\nh a pair\n```\npair a :: b\n```\n/* a\npair */\n*type two => pair\n"))])]))])));
+        for (line, character) in [(1.0, 5.0), (3.0, 1.0), (6.0, 1.0)] {    //Prose, fenced code and a block comment only mention the type.
+            assert_eq!(server.handle(&request("textDocument/hover", at(line, character)))[0].get("result"), &Json::Null);
+        }
+        assert!(server.handle(&request("textDocument/hover", at(7.0, 14.0)))[0].get("result").get("contents") != &Json::Null);
+        assert_eq!(server.handle(&request("nonsense", Json::Null))[0].get("error").get("code"), &Json::Number(-32601.0));
     }
 
-}
\ No newline at end of file
+}
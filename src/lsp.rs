@@ -0,0 +1,650 @@
+//Module for the language server, which lets editors check diazo files and look up their types while they are being written:
+// A - JSON values, read and written by hand like the JSON exporter's output
+// B - Messages, framed by a Content-Length header as the protocol asks
+// C - Positions, between the lexer's lines and words and the editor's lines and characters
+// D - The server itself: documents, diagnostics, completion, hover and go-to-definition
+
+//A - Define JSON values, enough for the messages of the protocol.
+pub mod value {
+    use std::fmt;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Json {
+        Null,
+        Bool(bool),
+        Number(f64),
+        String(String),
+        Array(Vec<Json>),
+        Object(Vec<(String, Json)>)    //Keys are kept in the order they were written.
+    }
+
+    const NULL: Json = Json::Null;
+
+    impl Json {
+        //Build an object from its keys and values.
+        pub fn object(pairs: Vec<(&str, Json)>) -> Json {
+            Json::Object(pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+        }
+
+        pub fn string(s: &str) -> Json {
+            Json::String(s.to_string())
+        }
+
+        //Look up a key of an object. Anything missing, or looked up on something other than an object, is null.
+        pub fn get(&self, key: &str) -> &Json {
+            match self {
+                Json::Object(pairs) => pairs.iter().find(|(k, _)| k == key).map_or(&NULL, |(_, v)| v),
+                _ => &NULL
+            }
+        }
+
+        pub fn as_str(&self) -> Option<&str> {
+            match self {
+                Json::String(s) => Some(s),
+                _ => None
+            }
+        }
+
+        pub fn as_usize(&self) -> Option<usize> {
+            match self {
+                Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+                _ => None
+            }
+        }
+
+        pub fn as_array(&self) -> &[Json] {
+            match self {
+                Json::Array(v) => v,
+                _ => &[]
+            }
+        }
+    }
+
+    impl fmt::Display for Json {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Json::Null => write!(f, "null"),
+                Json::Bool(b) => write!(f, "{}", b),
+                Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+                Json::Number(n) => write!(f, "{}", n),
+                Json::String(s) => write!(f, "{}", crate::exporters::json::string(s)),
+                Json::Array(v) => write!(f, "[{}]", v.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(",")),
+                Json::Object(pairs) => write!(f, "{{{}}}", pairs.iter().map(|(k, v)| format!("{}:{}", crate::exporters::json::string(k), v)).collect::<Vec<_>>().join(","))
+            }
+        }
+    }
+
+    //Read a JSON value, which must be the whole of the input apart from whitespace.
+    pub fn parse(input: &str) -> Result<Json, String> {
+        let mut reader = Reader { input: input.as_bytes(), at: 0 };
+        let value = reader.value()?;
+        reader.whitespace();
+        if reader.at != reader.input.len() {
+            return Err(format!("Unexpected text after the value at byte {}.", reader.at))
+        }
+        Ok(value)
+    }
+
+    //The head reading through the input.
+    struct Reader<'a> {
+        input: &'a [u8],
+        at: usize
+    }
+
+    impl Reader<'_> {
+        fn whitespace(&mut self) {
+            while matches!(self.input.get(self.at), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+                self.at += 1;
+            }
+        }
+
+        fn error<T>(&self, message: &str) -> Result<T, String> {
+            Err(format!("{} at byte {}.", message, self.at))
+        }
+
+        //Step over some expected text.
+        fn expect(&mut self, text: &str) -> Result<(), String> {
+            if self.input[self.at..].starts_with(text.as_bytes()) {
+                self.at += text.len();
+                Ok(())
+            } else {
+                self.error(&format!("Expected {}", text))
+            }
+        }
+
+        fn value(&mut self) -> Result<Json, String> {
+            self.whitespace();
+            match self.input.get(self.at) {
+                Some(b'n') => self.expect("null").map(|_| Json::Null),
+                Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+                Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+                Some(b'"') => self.string().map(Json::String),
+                Some(b'[') => {
+                    self.at += 1;
+                    let mut items: Vec<Json> = Vec::new();
+                    self.whitespace();
+                    if self.input.get(self.at) == Some(&b']') {
+                        self.at += 1;
+                        return Ok(Json::Array(items))
+                    }
+                    loop {
+                        items.push(self.value()?);
+                        self.whitespace();
+                        match self.input.get(self.at) {
+                            Some(b',') => self.at += 1,
+                            Some(b']') => {
+                                self.at += 1;
+                                return Ok(Json::Array(items))
+                            },
+                            _ => return self.error("Expected , or ]")
+                        }
+                    }
+                },
+                Some(b'{') => {
+                    self.at += 1;
+                    let mut pairs: Vec<(String, Json)> = Vec::new();
+                    self.whitespace();
+                    if self.input.get(self.at) == Some(&b'}') {
+                        self.at += 1;
+                        return Ok(Json::Object(pairs))
+                    }
+                    loop {
+                        self.whitespace();
+                        if self.input.get(self.at) != Some(&b'"') {
+                            return self.error("Expected a key")
+                        }
+                        let key = self.string()?;
+                        self.whitespace();
+                        self.expect(":")?;
+                        pairs.push((key, self.value()?));
+                        self.whitespace();
+                        match self.input.get(self.at) {
+                            Some(b',') => self.at += 1,
+                            Some(b'}') => {
+                                self.at += 1;
+                                return Ok(Json::Object(pairs))
+                            },
+                            _ => return self.error("Expected , or }")
+                        }
+                    }
+                },
+                Some(b'-' | b'0'..=b'9') => {
+                    let start = self.at;
+                    while matches!(self.input.get(self.at), Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+                        self.at += 1;
+                    }
+                    match std::str::from_utf8(&self.input[start..self.at]).ok().and_then(|n| n.parse::<f64>().ok()) {
+                        Some(n) => Ok(Json::Number(n)),
+                        None => self.error("Invalid number")
+                    }
+                },
+                _ => self.error("Expected a value")
+            }
+        }
+
+        //Read a string literal, starting at its opening quote.
+        fn string(&mut self) -> Result<String, String> {
+            self.at += 1;
+            let mut output: Vec<u8> = Vec::new();
+            loop {
+                match self.input.get(self.at) {
+                    None => return self.error("Unterminated string"),
+                    Some(b'"') => {
+                        self.at += 1;
+                        return String::from_utf8(output).or_else(|_| self.error("Invalid UTF-8 in string"))
+                    },
+                    Some(b'\\') => {
+                        self.at += 1;
+                        let c = match self.input.get(self.at) {
+                            Some(b'"') => '"',
+                            Some(b'\\') => '\\',
+                            Some(b'/') => '/',
+                            Some(b'b') => '\u{8}',
+                            Some(b'f') => '\u{c}',
+                            Some(b'n') => '\n',
+                            Some(b'r') => '\r',
+                            Some(b't') => '\t',
+                            Some(b'u') => {
+                                let high = self.hex()?;
+                                let code = if (0xd800..0xdc00).contains(&high) {   //The first half of a surrogate pair, which has to be followed by the second.
+                                    self.at += 1;
+                                    self.expect("\\u")?;
+                                    self.at -= 1;
+                                    let low = self.hex()?;
+                                    0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+                                } else {
+                                    high
+                                };
+                                char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                            },
+                            _ => return self.error("Invalid escape")
+                        };
+                        self.at += 1;
+                        output.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+                    },
+                    Some(b) => {
+                        output.push(*b);
+                        self.at += 1;
+                    }
+                }
+            }
+        }
+
+        //Read the four hex digits of a \u escape, with the head on the u. Leaves the head on the last digit.
+        fn hex(&mut self) -> Result<u32, String> {
+            let digits = self.input.get(self.at + 1..self.at + 5).and_then(|d| std::str::from_utf8(d).ok()).and_then(|d| u32::from_str_radix(d, 16).ok());
+            match digits {
+                Some(d) => {
+                    self.at += 4;
+                    Ok(d)
+                },
+                None => self.error("Invalid \\u escape")
+            }
+        }
+    }
+}
+
+//B - Read and write messages. Each is a JSON body after a Content-Length header and a blank line.
+pub mod messages {
+    use std::io::{self, BufRead, Write};
+
+    //Read the next message's body, or None once the input has ended.
+    pub fn read(input: &mut impl BufRead) -> io::Result<Option<String>> {
+        let mut length: Option<usize> = None;
+        loop {
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(None)
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                if length.is_some() {   //The end of the headers.
+                    break
+                }
+                continue
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("Content-Length") {
+                    length = value.trim().parse().ok();
+                }
+            }
+        }
+        let mut body = vec![0; length.unwrap_or(0)];
+        input.read_exact(&mut body)?;
+        Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+    }
+
+    pub fn write(output: &mut impl Write, body: &str) -> io::Result<()> {
+        write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        output.flush()
+    }
+}
+
+//C - Convert between positions. Editors count lines from 0 and characters in UTF-16, the lexer counts lines from 1 and words in a line from 1.
+pub mod positions {
+    use crate::core::syntax::{SyntaxKind, SyntaxNode};
+
+    //A range in a line, as (line, first character, character after the last).
+    pub type Range = (usize, usize, usize);
+
+    //Length of some text in UTF-16, which is how editors count characters.
+    pub fn width(s: &str) -> usize {
+        s.encode_utf16().count()
+    }
+
+    //The range covered by a word of a line, counted the same way as the lexer: whitespace separates words, and every tab is a word of its own.
+    pub fn word(line: &str, word: usize) -> Option<(usize, usize)> {
+        let mut count: usize = 0;
+        let mut start: Option<usize> = None;    //Where the word being read started.
+        let mut at: usize = 0;
+        for c in line.chars().chain(std::iter::once(' ')) {
+            if c.is_whitespace() {
+                if let Some(s) = start.take() {
+                    count += 1;
+                    if count == word {
+                        return Some((s, at))
+                    }
+                }
+                if c == '\t' {
+                    count += 1;
+                    if count == word {
+                        return Some((at, at + 1))
+                    }
+                }
+            } else if start.is_none() {
+                start = Some(at);
+            }
+            at += c.len_utf16();
+        }
+        None
+    }
+
+    //Where a column of a line, counted in characters from 1 as spans are, starts in UTF-16.
+    pub fn column(line: &str, column: usize) -> usize {
+        line.chars().take(column.saturating_sub(1)).map(char::len_utf16).sum()
+    }
+
+    //The range of a whole line, leaving out the indentation.
+    pub fn line(line: &str) -> (usize, usize) {
+        (width(line) - width(line.trim_start()), width(line.trim_end()))
+    }
+
+    //The leaf of a line's syntax tree under a character, along with where it starts and ends.
+    pub fn leaf_at(line: &SyntaxNode, character: usize) -> Option<(&SyntaxNode, usize, usize)> {
+        let mut at: usize = 0;
+        for l in line.leaves() {
+            let end = at + width(&l.text);
+            if at <= character && character <= end && !matches!(l.kind, SyntaxKind::Whitespace | SyntaxKind::Indent | SyntaxKind::Newline) {
+                return Some((l, at, end))
+            }
+            at = end;
+        }
+        None
+    }
+
+    //The path of a file:// URI. Anything else is used as it is.
+    pub fn path(uri: &str) -> String {
+        let path = match uri.strip_prefix("file://") {
+            Some(p) => p,
+            None => return uri.to_string()
+        };
+        let bytes = path.as_bytes();
+        let mut output: Vec<u8> = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            match (bytes[i], path.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok())) {
+                (b'%', Some(b)) => {
+                    output.push(b);
+                    i += 3;
+                },
+                (b, _) => {
+                    output.push(b);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&output).into_owned()
+    }
+
+    //The file:// URI of a path.
+    pub fn uri(path: &str) -> String {
+        let mut output = String::from("file://");
+        for b in path.bytes() {
+            match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => output.push(b as char),
+                b => output.push_str(&format!("%{:02X}", b))
+            }
+        }
+        output
+    }
+}
+
+//D - The server. Every document is read again from the top whenever it changes, which is quick enough for notes.
+pub mod server {
+    use std::collections::HashMap;
+    use std::io::{self, BufRead, Write};
+    use std::path::{Path, PathBuf};
+    use std::fs;
+    use crate::{lexer, cst, filehandling, pipeline::{self, Analysis}};
+    use crate::core::{errors::{DiazoError, Location}, imports::ImportGraph, interpreter_structs::TypeRegistry, syntax::{SyntaxKind, SyntaxNode}};
+    use super::{messages, positions, value::{self, Json}};
+
+    //Error codes from JSON-RPC.
+    const PARSE_ERROR: f64 = -32700.0;
+    const METHOD_NOT_FOUND: f64 = -32601.0;
+
+    pub struct Server {
+        documents: HashMap<String, String>,     //The text of every open document, by URI.
+        search_path: Vec<PathBuf>,
+        prelude: bool,
+        shut_down: bool                         //Whether the client has asked the server to shut down, as it should before exiting.
+    }
+
+    impl Server {
+        pub fn new(search_path: Vec<PathBuf>, prelude: bool) -> Server {
+            Server { documents: HashMap::new(), search_path, prelude, shut_down: false }
+        }
+
+        //Answer messages until the client says to exit or the input ends. Returns whether the client shut the server down first, as it should.
+        pub fn run(&mut self, input: &mut impl BufRead, output: &mut impl Write) -> io::Result<bool> {
+            while let Some(body) = messages::read(input)? {
+                let replies = match value::parse(&body) {
+                    Ok(message) if message.get("method").as_str() == Some("exit") => return Ok(self.shut_down),
+                    Ok(message) => self.handle(&message),
+                    Err(e) => vec![error(Json::Null, PARSE_ERROR, &e)]
+                };
+                for i in replies {
+                    messages::write(output, &i.to_string())?;
+                }
+            }
+            Ok(self.shut_down)
+        }
+
+        //Answer a single message, returning everything to send back. Notifications may get nothing back, or diagnostics.
+        pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+            let id = message.get("id").clone();
+            let params = message.get("params");
+            let uri = params.get("textDocument").get("uri").as_str().unwrap_or("").to_string();
+            let position = (params.get("position").get("line").as_usize().unwrap_or(0), params.get("position").get("character").as_usize().unwrap_or(0));
+            match message.get("method").as_str().unwrap_or("") {
+                "initialize" => vec![response(id, Json::object(vec![
+                    ("capabilities", Json::object(vec![
+                        ("textDocumentSync", Json::object(vec![("openClose", Json::Bool(true)), ("change", Json::Number(1.0)), ("save", Json::Bool(true))])),  //Whole documents are sent on each change.
+                        ("completionProvider", Json::object(vec![])),
+                        ("hoverProvider", Json::Bool(true)),
+                        ("definitionProvider", Json::Bool(true))
+                    ])),
+                    ("serverInfo", Json::object(vec![("name", Json::string("diazo")), ("version", Json::string(env!("CARGO_PKG_VERSION")))]))
+                ]))],
+                "shutdown" => {
+                    self.shut_down = true;
+                    vec![response(id, Json::Null)]
+                },
+                "textDocument/didOpen" => {
+                    self.documents.insert(uri.clone(), params.get("textDocument").get("text").as_str().unwrap_or("").to_string());
+                    vec![self.diagnostics(&uri)]
+                },
+                "textDocument/didChange" => {
+                    if let Some(text) = params.get("contentChanges").as_array().last().and_then(|c| c.get("text").as_str()) {
+                        self.documents.insert(uri.clone(), text.to_string());
+                    }
+                    vec![self.diagnostics(&uri)]
+                },
+                "textDocument/didSave" => vec![self.diagnostics(&uri)],    //Files it imports might have changed too.
+                "textDocument/didClose" => {
+                    self.documents.remove(&uri);
+                    vec![notification("textDocument/publishDiagnostics", Json::object(vec![("uri", Json::string(&uri)), ("diagnostics", Json::Array(Vec::new()))]))]
+                },
+                "textDocument/completion" => vec![response(id, self.completion(&uri))],
+                "textDocument/hover" => vec![response(id, self.hover(&uri, position))],
+                "textDocument/definition" => vec![response(id, self.definition(&uri, position))],
+                method if id != Json::Null => vec![error(id, METHOD_NOT_FOUND, &format!("Unknown method: {}", method))],
+                _ => Vec::new()     //Notifications that aren't needed, such as initialized.
+            }
+        }
+
+        //Read a document as it is in the editor.
+        fn analyse(&self, uri: &str) -> Option<Analysis> {
+            let text = self.documents.get(uri)?;
+            let config = lexer::LexerConfig { search_path: self.search_path.clone(), prelude: self.prelude, ..lexer::LexerConfig::file(&positions::path(uri)) };
+            Some(pipeline::analyse(text.clone(), &config))
+        }
+
+        fn diagnostics(&self, uri: &str) -> Json {
+            let text = self.documents.get(uri).map_or("", |t| t.as_str());
+            let path = positions::path(uri);
+            let diagnostics: Vec<Json> = self.analyse(uri).map_or(Vec::new(), |a| a.errors).iter().map(|e| {
+                let (in_document, (line, start, end)) = locate(e, &path, text);
+                Json::object(vec![
+                    ("range", range((line, start, end))),
+                    ("severity", Json::Number(1.0)),
+                    ("code", Json::string(e.kind().code())),
+                    ("source", Json::string("diazo")),
+                    ("message", Json::string(&if in_document { e.message().to_string() } else { e.to_string() }))    //Errors found elsewhere say where.
+                ])
+            }).collect();
+            notification("textDocument/publishDiagnostics", Json::object(vec![("uri", Json::string(uri)), ("diagnostics", Json::Array(diagnostics))]))
+        }
+
+        //Every type the document knows about, from its own declarations and its imports.
+        fn completion(&self, uri: &str) -> Json {
+            let analysis = match self.analyse(uri) {
+                Some(a) => a,
+                None => return Json::Array(Vec::new())
+            };
+            Json::Array(analysis.types.iter().map(|t| Json::object(vec![
+                ("label", Json::string(&t.name)),
+                ("kind", Json::Number(7.0)),    //Class, the closest of the protocol's kinds to a type.
+                ("detail", Json::string(&signature(&t.name, &analysis.types)))
+            ])).collect())
+        }
+
+        //The declaration of the type under the cursor.
+        fn hover(&self, uri: &str, position: (usize, usize)) -> Json {
+            let (analysis, name, start, end) = match self.type_at(uri, position) {
+                Some(t) => t,
+                None => return Json::Null
+            };
+            let mut contents = format!("```\n{}\n```", signature(&name, &analysis.types));
+            if let Some((file, _)) = declaration(uri, &name, &analysis.graph, &self.documents) {
+                contents.push_str(&format!("\nDeclared in {}", if file == uri { "this file".to_string() } else { positions::path(&file) }));
+            } else if analysis.graph.index(filehandling::STD).is_some() {
+                contents.push_str("\nDeclared in the standard library");
+            }
+            Json::object(vec![
+                ("contents", Json::object(vec![("kind", Json::string("markdown")), ("value", Json::string(&contents))])),
+                ("range", range((position.0, start, end)))
+            ])
+        }
+
+        //The *type line of the type under the cursor, which may be in an imported file. Types from the standard library aren't in any file.
+        fn definition(&self, uri: &str, position: (usize, usize)) -> Json {
+            let (analysis, name, _, _) = match self.type_at(uri, position) {
+                Some(t) => t,
+                None => return Json::Null
+            };
+            match declaration(uri, &name, &analysis.graph, &self.documents) {
+                Some((file, r)) => Json::object(vec![("uri", Json::string(&file)), ("range", range(r))]),
+                None => Json::Null
+            }
+        }
+
+        //The declared type named by the word under the cursor, with the range of the word. Only words where a type can be named count,
+        //which are the first word of an expression and the names in a declaration. The whole document is read so fences and block comments are known.
+        fn type_at(&self, uri: &str, (line, character): (usize, usize)) -> Option<(Analysis, String, usize, usize)> {
+            let tree = cst::cst(self.documents.get(uri)?);
+            let node = tree.children.get(line)?;
+            let (leaf, start, end) = positions::leaf_at(node, character)?;
+            let first = node.leaves().into_iter().find(|l| !matches!(l.kind, SyntaxKind::Whitespace | SyntaxKind::Indent));
+            let typed = match leaf.kind {
+                SyntaxKind::TypeName | SyntaxKind::Parameter => node.kind == SyntaxKind::DeclarationLine,
+                SyntaxKind::Word => node.kind == SyntaxKind::ExpressionLine && first.is_some_and(|f| std::ptr::eq(f, leaf)),
+                _ => false
+            };
+            if !typed {
+                return None
+            }
+            let analysis = self.analyse(uri)?;
+            analysis.types.get(&leaf.text)?;
+            let name = leaf.text.clone();
+            Some((analysis, name, start, end))
+        }
+    }
+
+    //Where an error belongs in a document, and whether it was found in the document rather than in something it imports.
+    fn locate(e: &DiazoError, path: &str, text: &str) -> (bool, positions::Range) {
+        let lines: Vec<&str> = text.lines().collect();
+        let whole = |line: usize| {
+            let (start, end) = positions::line(lines.get(line).copied().unwrap_or(""));
+            (line, start, end)
+        };
+        match e {
+            DiazoError::Lexer { location, .. } if location.file.as_deref().is_none_or(|f| f == path) => {
+                let line = location.line.saturating_sub(1);
+                let range = match location.word.and_then(|w| positions::word(lines.get(line).copied().unwrap_or(""), w)) {
+                    Some((start, end)) => (line, start, end),
+                    None => whole(line)
+                };
+                (true, range)
+            },
+            //The parser points at a token, which runs to the next whitespace, and the interpreter at the part of an expression that failed, which runs to the end of its line.
+            DiazoError::Parser { span, .. } | DiazoError::Interpreter { span, .. } if span.file.is_none() => {
+                let line = span.line.saturating_sub(1);
+                let text = lines.get(line).copied().unwrap_or("");
+                let start = positions::column(text, span.column);
+                let end = match e {
+                    DiazoError::Parser { .. } => start + text.chars().skip(span.column.saturating_sub(1)).enumerate()
+                        .take_while(|(i, c)| *i == 0 || !c.is_whitespace()).map(|(_, c)| c.len_utf16()).sum::<usize>(),
+                    _ => positions::line(text).1.max(start)
+                };
+                (true, (line, start, end))
+            },
+            DiazoError::Lexer { location: Location { file, .. }, .. } | DiazoError::Parser { file, .. } | DiazoError::Interpreter { file, .. } => (false, whole(use_line(text, file.as_deref()))),
+            DiazoError::Io { file, .. } => (true, whole(use_line(text, Some(file))))
+        }
+    }
+
+    //The line of the *use that brought in a file, going by the file's name. Otherwise the first *use, or the first line if there are none.
+    fn use_line(text: &str, file: Option<&str>) -> usize {
+        let tree = cst::cst(text);
+        let uses: Vec<(usize, &SyntaxNode)> = tree.children.iter().enumerate().filter(|(_, l)| l.kind == SyntaxKind::UseLine).collect();
+        let named = |l: &SyntaxNode| {
+            let filename = l.leaves().into_iter().find(|f| f.kind == SyntaxKind::Filename).map(|f| f.text.clone());
+            match (file, filename) {
+                (Some(file), Some(filename)) => Path::new(file).file_name() == Path::new(&filename).file_name(),
+                _ => false
+            }
+        };
+        uses.iter().find(|(_, l)| named(l)).or(uses.first()).map_or(0, |(i, _)| *i)
+    }
+
+    //A type's declaration as it would be written, less the *type.
+    fn signature(name: &str, types: &TypeRegistry) -> String {
+        match types.get(name) {
+            Some(t) => format!("{} => {}{}", t.name, t.signature(), t.annotations.iter().map(|a| format!(" @{}", a)).collect::<String>()),
+            None => name.to_string()
+        }
+    }
+
+    //Find the *type line declaring a type, first in the document and then in the files it imports. Open documents are read as the editor has them.
+    fn declaration(uri: &str, name: &str, graph: &ImportGraph, documents: &HashMap<String, String>) -> Option<(String, positions::Range)> {
+        let mut files: Vec<String> = vec![uri.to_string()];
+        files.extend(graph.files.iter().filter(|f| f.as_str() != filehandling::STD).map(|f| positions::uri(f)));
+        for file in files {
+            let text = match documents.get(&file) {
+                Some(t) => t.clone(),
+                None => match fs::read_to_string(positions::path(&file)) {
+                    Ok(t) => t,
+                    Err(_) => continue
+                }
+            };
+            for (i, l) in cst::cst(&text).children.iter().enumerate().filter(|(_, l)| l.kind == SyntaxKind::DeclarationLine) {
+                let mut at: usize = 0;
+                for leaf in l.leaves() {
+                    if leaf.kind == SyntaxKind::TypeName && leaf.text == name {
+                        return Some((file, (i, at, at + positions::width(&leaf.text))))
+                    }
+                    at += positions::width(&leaf.text);
+                }
+            }
+        }
+        None
+    }
+
+    fn range((line, start, end): positions::Range) -> Json {
+        let position = |character: usize| Json::object(vec![("line", Json::Number(line as f64)), ("character", Json::Number(character as f64))]);
+        Json::object(vec![("start", position(start)), ("end", position(end))])
+    }
+
+    fn response(id: Json, result: Json) -> Json {
+        Json::object(vec![("jsonrpc", Json::string("2.0")), ("id", id), ("result", result)])
+    }
+
+    fn error(id: Json, code: f64, message: &str) -> Json {
+        Json::object(vec![("jsonrpc", Json::string("2.0")), ("id", id), ("error", Json::object(vec![("code", Json::Number(code)), ("message", Json::string(message))]))])
+    }
+
+    fn notification(method: &str, params: Json) -> Json {
+        Json::object(vec![("jsonrpc", Json::string("2.0")), ("method", Json::string(method)), ("params", params)])
+    }
+}
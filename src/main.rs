@@ -1,10 +1,261 @@
-use diazo::{lexer, filehandling, parser};
-
-fn main() {
-    let test = lexer::lexer(filehandling::read_file(&"prokaryotes.dz").expect("oops")).expect("you fucked up");
-    dbg!(&test);
-    let test2 = parser::parser(test).unwrap();
-    for i in test2 {
-        println!("{}", i.print());
+use diazo::{lexer, cst, formatter, filehandling, parser, interpreter, exporters, lsp, pipeline};
+use diazo::core::{errors, imports::ImportGraph};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: diazo <command> [options] <file>
+       diazo lsp [options]
+
+Commands:
+    check                       Report every error in the file.
+    tokens                      Print the tokens the lexer reads from the file.
+    cst                         Print the concrete syntax tree, which keeps comments and whitespace.
+    fmt [--check]               Rewrite the file in the canonical style.
+    lsp                         Serve editors over the Language Server Protocol on standard input and output.
+    ast                         Print the parser's output as JSON.
+    types                       List every type the file declares or imports.
+    export -f <format>          Render the file as html, markdown, latex, json, anki, csv or tsv.
+
+Options:
+    -f, --format <format>       The format for export.
+    -p, --path <dir>            Look for imports in this directory too. Searched before DIAZO_PATH.
+    --no-std                    Don't import the standard library before the file.
+    --indent <spaces>           Let this many spaces make up a level of indentation, as well as a tab. An *indent pragma in the file takes precedence.
+    --check                     For fmt, change nothing and fail if the file isn't formatted.
+    -o, --output <path>         Write an export here instead of to standard output. For csv and tsv, the directory to write the tables in.
+    -h, --help                  Print this message.
+
+Exit codes: 0 on success, 1 if the file has errors or, for fmt --check, needs formatting, 2 if the command couldn't be run.";
+
+const FORMATS: [&str; 9] = ["html", "markdown", "md", "latex", "tex", "json", "anki", "csv", "tsv"];
+
+//Everything given on the command line.
+struct Options {
+    command: String,
+    file: String,
+    search_path: Vec<PathBuf>,
+    prelude: bool,
+    format: Option<String>,
+    output: Option<String>,
+    check: bool,
+    indent_spaces: Option<usize>
+}
+
+fn main() -> ExitCode {
+    let options = match read_arguments(env::args().skip(1).collect()) {
+        Ok(o) => o,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("{}\n", message);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2)
+        }
+    };
+    if options.command == "lsp" {   //Documents come from the editor rather than a file.
+        let mut server = lsp::server::Server::new(options.search_path, options.prelude);
+        return match server.run(&mut io::stdin().lock(), &mut io::stdout().lock()) {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => ExitCode::from(1),     //The client exited without shutting the server down first.
+            Err(e) => {
+                eprintln!("Could not talk to the client: {}", e);
+                ExitCode::from(2)
+            }
+        }
+    }
+    let input = match filehandling::read_file(&options.file) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Could not read {}: {}", options.file, e);
+            return ExitCode::from(2)
+        }
+    };
+    match options.command.as_str() {    //Commands that read without the lexer, so nothing in the file can fail.
+        "cst" => {
+            emit(&cst::cst(&input).print());
+            return ExitCode::SUCCESS
+        },
+        "fmt" => return format(&input, &options),
+        _ => ()
+    }
+    let config = lexer::LexerConfig { search_path: options.search_path.clone(), prelude: options.prelude, indent_spaces: options.indent_spaces, ..lexer::LexerConfig::file(&options.file) };
+
+    match options.command.as_str() {
+        "tokens" => {
+            let (tokens, error_list, graph) = lexer::lexer_recovering_with(input, &config);
+            for i in &tokens {  //Each token is printed after the file, line and column it was found at.
+                let file = i.span.file.map_or(options.file.as_str(), |f| graph.files[f].as_str());
+                emit(&format!("{}:{}:{}\t{}\n", file, i.span.line, i.span.column, i.value.print()));
+            }
+            finish(error_list)
+        },
+        "ast" => {
+            let (tokens, mut error_list, graph) = lexer::lexer_recovering_with(input, &config);
+            let (ir, parser_errors) = parser::parser_recovering(tokens);
+            error_list.extend(parser_errors);
+            name_files(&mut error_list, &graph, &options);
+            emit(&exporters::json::ast(&ir));
+            finish(error_list)
+        },
+        "types" => {
+            let (tokens, mut error_list, graph) = lexer::lexer_recovering_with(input, &config);
+            let (ir, parser_errors) = parser::parser_recovering(tokens);
+            error_list.extend(parser_errors);
+            match interpreter::registry(&ir) {
+                Ok(types) => for t in types.iter() {
+                    let annotations: String = t.annotations.iter().map(|a| " @".to_string() + a).collect();
+                    emit(&format!("{} => {}{}\n", t.name, t.signature(), annotations));
+                },
+                Err(e) => error_list.push(e)
+            }
+            name_files(&mut error_list, &graph, &options);
+            finish(error_list)
+        },
+        "check" => {
+            let analysis = pipeline::analyse(input, &config);
+            if analysis.errors.is_empty() {
+                emit(&format!("{}: no errors found.\n", options.file));
+            }
+            finish(analysis.errors)
+        },
+        "export" => export(input, &config, &options),
+        other => {
+            eprintln!("Unknown command: {}\n\n{}", other, USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
+
+//Read the command line. An empty error asks for the usage message alone.
+fn read_arguments(arguments: Vec<String>) -> Result<Options, String> {
+    let mut positional: Vec<String> = Vec::new();
+    let mut options = Options { command: String::new(), file: String::new(), search_path: Vec::new(), prelude: true, format: None, output: None, check: false, indent_spaces: None };
+    let mut arguments = arguments.into_iter();
+    while let Some(arg) = arguments.next() {
+        //Local function for reading the value that follows a flag.
+        fn value(flag: &str, next: Option<String>) -> Result<String, String> {
+            next.ok_or(format!("{} needs a value.", flag))
+        }
+        match arg.as_str() {
+            "-h" | "--help" | "help" => return Err(String::new()),
+            "-p" | "--path" => options.search_path.push(PathBuf::from(value(&arg, arguments.next())?)),
+            "--no-std" => options.prelude = false,
+            "--check" => options.check = true,
+            "--indent" => match value(&arg, arguments.next())?.parse::<usize>() {
+                Ok(n) if n > 0 => options.indent_spaces = Some(n),
+                _ => return Err("--indent needs a number of spaces of at least 1.".to_string())
+            },
+            "-f" | "--format" => options.format = Some(value(&arg, arguments.next())?),
+            "-o" | "--output" => options.output = Some(value(&arg, arguments.next())?),
+            flag if flag.starts_with('-') && flag.len() > 1 => return Err(format!("Unknown option: {}", flag)),
+            _ => positional.push(arg)
+        }
+    }
+    options.search_path.extend(filehandling::search_path());
+    match positional.as_slice() {
+        [command, file] => {
+            options.command = command.clone();
+            options.file = file.clone();
+            Ok(options)
+        },
+        [command] if command == "lsp" => {
+            options.command = command.clone();
+            Ok(options)
+        },
+        [] => Err(String::new()),
+        [_] => Err("No file given.".to_string()),
+        _ => Err("Too many arguments.".to_string())
+    }
+}
+
+//Render the file in the format asked for. Nothing is written if the file has errors.
+fn export(input: String, config: &lexer::LexerConfig, options: &Options) -> ExitCode {
+    let format = match options.format.as_deref() {
+        Some(f) => f,
+        None => {
+            eprintln!("export needs a --format.\n\n{}", USAGE);
+            return ExitCode::from(2)
+        }
+    };
+    if !FORMATS.contains(&format) {
+        eprintln!("Unknown format: {}\n\n{}", format, USAGE);
+        return ExitCode::from(2)
+    }
+    let pipeline::Analysis { objects, types, errors, .. } = pipeline::analyse(input, config);
+    if !errors.is_empty() {
+        return finish(errors)
+    }
+    let output = match format {
+        "html" => exporters::html::html(&objects),
+        "markdown" | "md" => exporters::markdown::markdown(&objects),
+        "latex" | "tex" => exporters::latex::latex(&objects),
+        "json" => exporters::json::objects(&objects),
+        "anki" => exporters::anki::anki(&objects, &types),
+        "csv" | "tsv" => {  //One file for each table, so these go in a directory rather than to standard output.
+            let dir = PathBuf::from(options.output.as_deref().unwrap_or("."));
+            for i in exporters::csv::tables(&objects, if format == "tsv" { '\t' } else { ',' }) {
+                let path = dir.join(&i.name);
+                if let Err(e) = fs::write(&path, i.contents) {
+                    eprintln!("Could not write {}: {}", path.display(), e);
+                    return ExitCode::from(2)
+                }
+                emit(&format!("{}\n", path.display()));
+            }
+            return ExitCode::SUCCESS
+        },
+        _ => unreachable!()  //Checked against FORMATS above.
+    };
+    match &options.output {
+        Some(path) => if let Err(e) = fs::write(path, output) {
+            eprintln!("Could not write {}: {}", path, e);
+            return ExitCode::from(2)
+        },
+        None => emit(&output)
+    }
+    ExitCode::SUCCESS
+}
+
+//Format the file in place, or with --check only report whether it needs it.
+fn format(input: &str, options: &Options) -> ExitCode {
+    let formatted = formatter::format_with(input, options.indent_spaces);
+    if formatted == input {
+        return ExitCode::SUCCESS
+    }
+    if options.check {
+        eprintln!("{} is not formatted.", options.file);
+        return ExitCode::from(1)
+    }
+    if let Err(e) = fs::write(&options.file, formatted) {
+        eprintln!("Could not write {}: {}", options.file, e);
+        return ExitCode::from(2)
+    }
+    ExitCode::SUCCESS
+}
+
+//Name the files that errors from the parser and interpreter are in, which they only know by their place in the import graph.
+fn name_files(error_list: &mut [errors::DiazoError], graph: &ImportGraph, options: &Options) {
+    for e in error_list {
+        e.name_file(graph, Some(&options.file));
+    }
+}
+
+//Write to standard output. Failures, such as the reader of a pipe going away early, are ignored rather than ending in a panic.
+fn emit(text: &str) {
+    let _ = io::stdout().lock().write_all(text.as_bytes());
+}
+
+//Report every error found and choose the exit code from whether there were any.
+fn finish(error_list: Vec<errors::DiazoError>) -> ExitCode {
+    for e in &error_list {
+        eprintln!("{}\n", e);
+    }
+    if error_list.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        eprintln!("{} error(s) found.", error_list.len());
+        ExitCode::from(1)
     }
 }
@@ -0,0 +1,82 @@
+//Run the diazo binary the way a user would, checking how it reads its arguments and the exit codes promised in its usage message.
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+//A directory of the test's own, removed when the test ends whether or not it passed.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let dir = std::env::temp_dir().join(format!("diazo_cli_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    //Write a file into the directory and give back its path.
+    fn file(&self, name: &str, contents: &str) -> String {
+        let path = self.0.join(name);
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn diazo(arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_diazo")).args(arguments).output().unwrap()
+}
+
+fn code(arguments: &[&str]) -> i32 {
+    diazo(arguments).status.code().unwrap()
+}
+
+#[test]
+fn arguments_that_cannot_be_run_exit_with_2() {
+    let dir = TempDir::new("arguments");
+    let file = dir.file("notes.dz", "h title\n");
+
+    let output = diazo(&[]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Usage: diazo"));
+    assert_eq!(code(&["--help"]), 2);
+    assert_eq!(code(&["check"]), 2);                                    //No file given.
+    assert_eq!(code(&["check", &file, "extra"]), 2);                    //Too many arguments.
+    assert_eq!(code(&["check", "--nonsense", &file]), 2);
+    assert_eq!(code(&["check", &file, "--path"]), 2);                   //A flag without its value.
+    assert_eq!(code(&["fmt", "--indent", "0", &file]), 2);
+    assert_eq!(code(&["fmt", "--indent", "two", &file]), 2);
+    assert_eq!(code(&["nonsense", &file]), 2);
+    assert_eq!(code(&["export", &file]), 2);                            //export needs a --format.
+    assert_eq!(code(&["export", "-f", "pdf", &file]), 2);
+    assert_eq!(code(&["check", &dir.0.join("missing.dz").to_string_lossy()]), 2);
+}
+
+#[test]
+fn errors_in_the_file_exit_with_1() {
+    let dir = TempDir::new("errors");
+    let valid = dir.file("valid.dz", "h title\ndef cell -> the basic unit of life\n");
+    let invalid = dir.file("invalid.dz", "*type h => c\n");
+    let unformatted = dir.file("unformatted.dz", "def cell  ->  life\n");
+
+    assert_eq!(code(&["check", &valid]), 0);
+    let output = diazo(&["check", &invalid]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).ends_with("1 error(s) found.\n"));
+    assert_eq!(code(&["check", "--no-std", &invalid]), 0);              //Without std, h is free to declare.
+    assert_eq!(code(&["export", "--format", "html", &invalid]), 1);
+
+    assert_eq!(code(&["fmt", "--check", &valid]), 0);
+    assert_eq!(code(&["fmt", "--check", &unformatted]), 1);
+    assert_eq!(code(&["fmt", &unformatted]), 0);
+    assert_eq!(fs::read_to_string(&unformatted).unwrap(), "def cell -> life\n");
+    assert_eq!(code(&["fmt", "--check", &unformatted]), 0);
+
+    let output = dir.0.join("valid.md");
+    assert_eq!(code(&["export", "-f", "md", "-o", output.to_str().unwrap(), &valid]), 0);
+    assert!(fs::read_to_string(output).unwrap().starts_with("# title"));
+}